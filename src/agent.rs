@@ -0,0 +1,247 @@
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::databases::Databases;
+use crate::{lookup, selected_profile, LookupError};
+
+/// Idle time in seconds after which the agent exits if no other timeout is configured
+const DEFAULT_TIMEOUT: u64 = 900;
+/// Time the agent waits for a client to send its request or read the response
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time a client waits for the response, which may include unlocking another database
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Options of `--agent`
+struct AgentOptions {
+    socket_path: String,
+    /// Profile of the database served, None for the default one
+    profile: Option<String>,
+    /// Idle timeout, None to run until killed
+    timeout: Option<Duration>,
+}
+
+/// Run the agent: unlock the database once and answer lookups on a Unix socket
+///
+/// summon starts one provider process per variable, so without the agent every
+/// lookup has to run the key derivation of the database again. Databases of other
+/// profiles are unlocked on their first lookup and kept as well.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let mut databases = Databases::new(options.profile.clone())?;
+    // Unlock the first database searched right away, asking for its password if necessary
+    let first = databases.search_order()?.into_iter().next().flatten();
+    databases.open(first.as_deref())?;
+    let identity = databases.identity();
+
+    let listener = bind(&options.socket_path)?;
+    eprintln!("summon-keepass agent listening on {}", options.socket_path);
+
+    let last_activity = Arc::new(Mutex::new(Instant::now()));
+    if let Some(timeout) = options.timeout {
+        watch_idle_timeout(Arc::clone(&last_activity), timeout, options.socket_path.clone());
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        *last_activity.lock().unwrap() = Instant::now();
+        // A misbehaving client must not take the agent down
+        let _ = serve(stream, &identity, &mut databases);
+        *last_activity.lock().unwrap() = Instant::now();
+    }
+
+    Ok(())
+}
+
+/// Query a running agent for a secret path, adding the warnings of the lookup to `warnings`
+///
+/// Returns None if the agent can not be reached, does not answer in time or serves
+/// another database or other options, so the caller can fall back to reading the
+/// database file.
+pub fn query(socket_path: &str, databases: &Databases, secret_path: &str,
+             warnings: &mut Vec<String>) -> Option<Result<Vec<u8>, LookupError>> {
+    let mut stream = UnixStream::connect(socket_path).ok()?;
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(RESPONSE_TIMEOUT)).ok()?;
+    // The secret path is length-prefixed, it may contain newlines
+    stream.write_all(format!("{}\n{}\n", databases.identity(), secret_path.len()).as_bytes()).ok()?;
+    stream.write_all(secret_path.as_bytes()).ok()?;
+    stream.flush().ok()?;
+    read_response(&mut BufReader::new(stream), warnings)
+}
+
+fn parse_options(args: &[String]) -> Result<AgentOptions, String> {
+    let mut socket_path = env::var("SUMMON_KEEPASS_AGENT_SOCK").ok();
+    let mut timeout = env::var("SUMMON_KEEPASS_AGENT_TIMEOUT").ok();
+    let mut profile = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--socket" => socket_path = Some(args.next().ok_or("--socket requires a path")?.clone()),
+            "--profile" => profile = Some(args.next().ok_or("--profile requires a profile name")?.clone()),
+            "--timeout" => timeout = Some(args.next().ok_or("--timeout requires a number of seconds")?.clone()),
+            _ => return Err(format!("Unknown agent option {}", arg)),
+        }
+    }
+
+    let socket_path = match socket_path {
+        Some(path) => path,
+        None => match env::var("XDG_RUNTIME_DIR") {
+            Ok(dir) => format!("{}/summon-keepass.sock", dir),
+            Err(_) => return Err(
+                "No agent socket configured: use --socket, SUMMON_KEEPASS_AGENT_SOCK or set XDG_RUNTIME_DIR".to_string()),
+        },
+    };
+
+    let timeout = match timeout {
+        Some(t) => t.parse::<u64>().map_err(|_| format!("Invalid agent timeout '{}', expected seconds", t))?,
+        None => DEFAULT_TIMEOUT,
+    };
+
+    Ok(AgentOptions {
+        socket_path,
+        profile: selected_profile(profile),
+        timeout: if timeout == 0 { None } else { Some(Duration::from_secs(timeout)) },
+    })
+}
+
+/// Bind the socket, accessible by the current user only
+fn bind(socket_path: &str) -> Result<UnixListener, String> {
+    if let Ok(metadata) = fs::symlink_metadata(socket_path) {
+        // A mistyped path must not remove a file of the user
+        if !metadata.file_type().is_socket() {
+            return Err(format!("Could not bind agent socket {}: the path exists and is not a socket", socket_path));
+        }
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(format!("An agent is already listening on {}", socket_path));
+        }
+        // Left behind by an agent that did not shut down cleanly
+        fs::remove_file(socket_path)
+            .map_err(|e| format!("Could not remove stale agent socket {}: {}", socket_path, e))?;
+    }
+
+    // The socket is created with the umask, so it is bound in a private directory and
+    // only linked to its path once other users can no longer connect
+    let private_dir = format!("{}.{}", socket_path, process::id());
+    fs::DirBuilder::new().mode(0o700).create(&private_dir)
+        .map_err(|e| format!("Could not create directory {} for the agent socket: {}", private_dir, e))?;
+    let private_socket = format!("{}/s", private_dir);
+    let result = bind_private(&private_socket, socket_path);
+    let _ = fs::remove_file(&private_socket);
+    let _ = fs::remove_dir(&private_dir);
+    result
+}
+
+fn bind_private(private_socket: &str, socket_path: &str) -> Result<UnixListener, String> {
+    let listener = UnixListener::bind(private_socket)
+        .map_err(|e| format!("Could not bind agent socket {}: {}", socket_path, e))?;
+    fs::set_permissions(private_socket, fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Could not restrict permissions of agent socket {}: {}", socket_path, e))?;
+    // Unlike a rename, linking never replaces a file created at the path in the meantime
+    fs::hard_link(private_socket, socket_path)
+        .map_err(|e| format!("Could not bind agent socket {}: {}", socket_path, e))?;
+
+    Ok(listener)
+}
+
+/// Exit the agent once no request arrived for `timeout`
+fn watch_idle_timeout(last_activity: Arc<Mutex<Instant>>, timeout: Duration, socket_path: String) {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(200));
+        if last_activity.lock().unwrap().elapsed() >= timeout {
+            let _ = fs::remove_file(&socket_path);
+            process::exit(0);
+        }
+    });
+}
+
+/// Answer a single request: the identity of the client's databases and options
+/// (see [`Databases::identity`]) terminated by a newline, then the length of the secret path
+/// in bytes terminated by a newline, followed by the secret path
+fn serve(mut stream: UnixStream, identity: &str, databases: &mut Databases) -> std::io::Result<()> {
+    // Requests are answered one at a time, a silent client must not block the others
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut requested_identity = String::new();
+    reader.read_line(&mut requested_identity)?;
+    let mut length = String::new();
+    reader.read_line(&mut length)?;
+    let length = length.trim_end_matches('\n').parse()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let mut secret_path = vec![0; length];
+    reader.read_exact(&mut secret_path)?;
+    let secret_path = String::from_utf8(secret_path)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    // Lookups with another primary database or other options are left to the client
+    if requested_identity.trim_end_matches('\n') != identity {
+        stream.write_all(b"UNAVAILABLE\n")?;
+        return stream.flush();
+    }
+
+    let mut warnings = Vec::new();
+    let result = lookup(databases, &secret_path, &mut warnings);
+    write_response(stream, &warnings, result)
+}
+
+/// Responses are `OK <length>` or `ERR <exit code> <length>`, followed by the value or message,
+/// or `UNAVAILABLE` if the agent does not serve the client's databases and options
+/// They are preceded by a `WARN <length>` line and the message for every warning of the lookup.
+fn write_response(mut stream: UnixStream, warnings: &[String], result: Result<Vec<u8>, LookupError>) -> std::io::Result<()> {
+    for warning in warnings {
+        stream.write_all(format!("WARN {}\n", warning.len()).as_bytes())?;
+        stream.write_all(warning.as_bytes())?;
+    }
+    match result {
+        Ok(value) => {
+            stream.write_all(format!("OK {}\n", value.len()).as_bytes())?;
+            stream.write_all(&value)?;
+        }
+        Err(error) => {
+            stream.write_all(format!("ERR {} {}\n", error.exit_code, error.message.len()).as_bytes())?;
+            stream.write_all(error.message.as_bytes())?;
+        }
+    }
+    stream.flush()
+}
+
+/// Read a response, adding its warnings to `warnings`
+fn read_response(reader: &mut BufReader<UnixStream>, warnings: &mut Vec<String>) -> Option<Result<Vec<u8>, LookupError>> {
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header: Vec<&str> = header.trim_end().split(' ').collect();
+
+        let result = match header.as_slice() {
+            ["WARN", length] => {
+                warnings.push(String::from_utf8(read_body(reader, length)?).ok()?);
+                continue;
+            }
+            ["OK", length] => Ok(read_body(reader, length)?),
+            ["ERR", exit_code, length] => {
+                let exit_code = exit_code.parse().ok()?;
+                let message = String::from_utf8(read_body(reader, length)?).ok()?;
+                Err(LookupError { exit_code, message })
+            }
+            _ => return None,
+        };
+        return Some(result);
+    }
+}
+
+fn read_body(reader: &mut BufReader<UnixStream>, length: &str) -> Option<Vec<u8>> {
+    let mut body = vec![0; length.parse().ok()?];
+    reader.read_exact(&mut body).ok()?;
+    Some(body)
+}
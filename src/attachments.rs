@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use keepass::Database;
+use uuid::Uuid;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::options::NameMatching;
+
+/// The attachments of the entries of a database: their names and references into
+/// the binaries of the database, by entry UUID and version
+///
+/// keepass does not keep the attachments of entries, so they are read from the
+/// XML of the database.
+pub struct Attachments {
+    /// The attachments of the current version followed by those of the history items
+    entries: HashMap<Uuid, Vec<Vec<(String, String)>>>,
+}
+
+impl Attachments {
+    /// Read the attachments of the entries and their history items from the XML of a database
+    pub fn parse(xml: &[u8]) -> Result<Attachments, String> {
+        let mut entries = HashMap::new();
+        // Open elements and the attachments of the entry being read
+        let mut path: Vec<String> = Vec::new();
+        let mut uuid = None;
+        let mut versions = vec![Vec::new()];
+        let mut history_item = Vec::new();
+        let mut attachment = (String::new(), String::new());
+
+        for event in EventReader::new(xml) {
+            let event = event.map_err(|e| format!("Could not read the attachments of the database: {}", e))?;
+            let in_history = path.iter().any(|name| name == "History");
+            match event {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    let is_reference = name.local_name == "Value" && path.last().is_some_and(|p| p == "Binary");
+                    if let Some(reference) = attributes.iter().find(|a| is_reference && a.name.local_name == "Ref") {
+                        attachment.1 = reference.value.clone();
+                    }
+                    path.push(name.local_name);
+                }
+                XmlEvent::Characters(text) => match parent(&path) {
+                    Some(("Entry", "UUID")) if !in_history => {
+                        uuid = BASE64.decode(text.trim()).ok().and_then(|bytes| Uuid::from_slice(&bytes).ok());
+                    }
+                    Some(("Binary", "Key")) => attachment.0 = text,
+                    _ => {}
+                },
+                XmlEvent::EndElement { .. } => {
+                    match path.pop().as_deref() {
+                        Some("Binary") if in_history => history_item.push(std::mem::take(&mut attachment)),
+                        Some("Binary") => versions[0].push(std::mem::take(&mut attachment)),
+                        Some("Entry") if in_history => versions.push(std::mem::take(&mut history_item)),
+                        Some("Entry") => {
+                            let entry = std::mem::replace(&mut versions, vec![Vec::new()]);
+                            if let Some(uuid) = uuid.take() {
+                                entries.insert(uuid, entry);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Attachments { entries })
+    }
+
+    /// The content of an attachment of an entry, None if the entry does not have it
+    ///
+    /// `history_item` selects a history item of the entry by its index in the database
+    /// instead of the current version.
+    pub fn get<'a>(&self, db: &'a Database, entry: Uuid, history_item: Option<usize>, name: &str,
+                   name_matching: NameMatching) -> Option<&'a [u8]> {
+        let attachments = self.entries.get(&entry)?.get(history_item.map_or(0, |index| index + 1))?;
+        let (_, reference) = name_matching.filter(attachments, |(n, _)| Some(n.as_str()), name).into_iter().next()?;
+
+        // KDBX 3 stores the binaries in the metadata, KDBX 4 in the inner header by index
+        db.meta.binaries.binaries.iter()
+            .find(|binary| binary.identifier.as_deref() == Some(reference.as_str()))
+            .map(|binary| binary.content.as_slice())
+            .or_else(|| reference.parse::<usize>().ok()
+                .and_then(|index| db.header_attachments.get(index))
+                .map(|attachment| attachment.content.as_slice()))
+    }
+}
+
+/// The names of the innermost element and its parent
+fn parent(path: &[String]) -> Option<(&str, &str)> {
+    match path {
+        [.., parent, element] => Some((parent.as_str(), element.as_str())),
+        _ => None,
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::time::SystemTime;
+
+use keepass::{Database, DatabaseKey};
+use keepass::db::Group;
+use uuid::Uuid;
+
+use crate::attachments::Attachments;
+use crate::options::{GroupFilter, LookupOptions, NameMatching};
+use crate::{build_database_key, list_profiles, load_config, load_ini_file, open_database, Profiles};
+
+/// Name referring to the primary database in prefixes and the search order
+const PRIMARY_NAME: &str = "default";
+
+/// The databases a secret path may refer to, each opened on first use
+///
+/// The primary database is the one of the selected profile, configured by all
+/// sources. Other profiles are referenced by a `<profile>:` prefix of the secret
+/// path and configured by their [db.<name>] section only.
+pub struct Databases {
+    profiles: Profiles,
+    pub options: LookupOptions,
+    /// Groups hidden in the primary database
+    filter: GroupFilter,
+    /// Opened databases by profile name, None for the primary database
+    opened: HashMap<Option<String>, Opened>,
+}
+
+/// An unlocked database with the file contents and key to read its attachments
+struct Opened {
+    db: Database,
+    data: Vec<u8>,
+    key: DatabaseKey,
+    path: String,
+    /// Modification time and size of the file when it was read
+    file_state: Option<(SystemTime, u64)>,
+    filter: GroupFilter,
+    /// Read from the XML of the database on first use
+    attachments: Option<Attachments>,
+}
+
+impl Databases {
+    pub fn new(primary: Option<String>) -> Result<Databases, String> {
+        let ini = load_ini_file();
+        let profiles = Profiles {
+            selected: primary,
+            available: ini.as_ref().map(list_profiles).unwrap_or_default(),
+        };
+        let options = LookupOptions::load(ini.as_ref(), &profiles.section())?;
+        let filter = GroupFilter::load(ini.as_ref(), &profiles.section(), true)?;
+
+        Ok(Databases {
+            profiles,
+            options,
+            filter,
+            opened: HashMap::new(),
+        })
+    }
+
+    /// Split the database prefix off a secret path
+    ///
+    /// Only `default` and the names of configured profiles are recognized as prefix,
+    /// so entry paths containing a colon keep working.
+    pub fn split_prefix<'a>(&self, secret_path: &'a str) -> (Option<&'a str>, &'a str) {
+        match secret_path.split_once(':') {
+            Some((name, path)) if name == PRIMARY_NAME || self.profiles.available.iter().any(|p| p == name) => {
+                (self.database(name), path)
+            }
+            _ => (None, secret_path),
+        }
+    }
+
+    /// The databases to search for a secret path without prefix, in order
+    ///
+    /// Without a configured search order only the primary database is searched.
+    pub fn search_order(&self) -> Result<Vec<Option<String>>, String> {
+        let search_order = match &self.options.search_order {
+            Some(order) => order,
+            None => return Ok(vec![None]),
+        };
+        search_order.iter()
+            .map(|name| {
+                if name == PRIMARY_NAME || self.profiles.available.contains(name) {
+                    Ok(self.database(name).map(|p| p.to_string()))
+                } else {
+                    Err(format!("Unknown database '{}' in search order, available profiles: {}",
+                        name, self.profiles.available.join(", ")))
+                }
+            })
+            .collect()
+    }
+
+    /// What a lookup depends on besides the secret path: the selected profile, the
+    /// canonical paths and hidden groups of the databases and the lookup options
+    ///
+    /// An agent only answers clients with the same identity.
+    pub fn identity(&self) -> String {
+        let canonical = |path: String| fs::canonicalize(&path).map(|p| p.display().to_string()).unwrap_or(path);
+        let ini = load_ini_file();
+        let path = env::var("SUMMON_KEEPASS_DB_PATH").ok()
+            .or_else(|| ini.as_ref()?.get_from(Some(self.profiles.section()), "path").map(|p| p.to_string()))
+            .map(canonical);
+        let others: Vec<_> = self.profiles.available.iter()
+            .map(|name| {
+                let section = format!("db.{}", name);
+                let path = ini.as_ref().and_then(|ini| ini.get_from(Some(section.as_str()), "path"))
+                    .map(|p| canonical(p.to_string()));
+                (name, path, GroupFilter::load(ini.as_ref(), &section, false).ok())
+            })
+            .collect();
+        format!("{:?} {:?} {:?} {:?} {:?}", self.profiles.selected, path, self.filter, others, self.options)
+    }
+
+    /// The name of a database as used in prefixes and the search order
+    pub fn name(&self, profile: Option<&str>) -> String {
+        profile.or(self.profiles.selected.as_deref()).unwrap_or(PRIMARY_NAME).to_string()
+    }
+
+    /// The database of a profile, None for the primary database, opening it if necessary
+    ///
+    /// A database whose file changed since it was read, like after rotating a
+    /// credential, is read again with the same key. Its configuration is only
+    /// loaded again if that key no longer unlocks it.
+    pub fn open(&mut self, profile: Option<&str>) -> Result<&Database, String> {
+        let key = profile.map(|p| p.to_string());
+        let changed = self.opened.get(&key).is_some_and(|opened| file_state(&opened.path) != opened.file_state);
+        if changed {
+            let opened = self.opened.remove(&key).unwrap();
+            if let Ok(reread) = self.read(opened.path, opened.key, opened.filter) {
+                self.opened.insert(key.clone(), reread);
+            }
+        }
+        if !self.opened.contains_key(&key) {
+            let (config, filter) = match profile {
+                Some(name) => (load_config(Some(name), false)?,
+                    GroupFilter::load(load_ini_file().as_ref(), &format!("db.{}", name), false)?),
+                None => (load_config(self.profiles.selected.as_deref(), true)?, self.filter.clone()),
+            };
+            let db_key = build_database_key(&config.db_key)?;
+            let opened = self.read(config.db_path, db_key, filter)?;
+            self.opened.insert(key.clone(), opened);
+        }
+        Ok(&self.opened[&key].db)
+    }
+
+    /// Read and unlock a database file, removing the groups hidden by `filter`
+    fn read(&self, path: String, key: DatabaseKey, filter: GroupFilter) -> Result<Opened, String> {
+        // Taken before reading, so changes while reading are noticed on the next lookup
+        let file_state = file_state(&path);
+        let (mut db, data) = open_database(&path, key.clone())?;
+        exclude_groups(&mut db, &filter, self.options.name_matching);
+        Ok(Opened { db, data, key, path, file_state, filter, attachments: None })
+    }
+
+    /// The content of an attachment of an entry, opening the database if necessary
+    ///
+    /// The database is decrypted again to read the attachments of the entries from its XML.
+    pub fn attachment(&mut self, profile: Option<&str>, entry: Uuid, history_item: Option<usize>,
+                      name: &str) -> Result<Option<&[u8]>, String> {
+        self.open(profile)?;
+        let name_matching = self.options.name_matching;
+        let opened = self.opened.get_mut(&profile.map(|p| p.to_string())).unwrap();
+        if opened.attachments.is_none() {
+            let xml = Database::get_xml(&mut opened.data.as_slice(), opened.key.clone())
+                .map_err(|e| format!("Could not read the attachments of the database: {}", e))?;
+            opened.attachments = Some(Attachments::parse(&xml)?);
+        }
+        let attachments = opened.attachments.as_ref().unwrap();
+        Ok(attachments.get(&opened.db, entry, history_item, name, name_matching))
+    }
+
+    /// The profile of a database name, None for the primary database
+    fn database<'a>(&self, name: &'a str) -> Option<&'a str> {
+        if name == PRIMARY_NAME || self.profiles.selected.as_deref() == Some(name) {
+            None
+        } else {
+            Some(name)
+        }
+    }
+}
+
+/// The modification time and size of a file, None if it can not be read
+fn file_state(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Remove the recycle bin, unless it is included, and the excluded groups from a database,
+/// so that no lookup, search or field reference finds their entries
+fn exclude_groups(db: &mut Database, filter: &GroupFilter, name_matching: NameMatching) {
+    let recycle_bin = db.meta.recyclebin_uuid.filter(|_| !filter.include_recycle_bin);
+    remove_groups(&mut db.root, &mut Vec::new(), recycle_bin, &filter.excluded_groups, name_matching);
+}
+
+fn remove_groups(group: &mut Group, group_path: &mut Vec<String>, recycle_bin: Option<Uuid>,
+                 excluded_groups: &[Vec<String>], name_matching: NameMatching) {
+    group.groups.retain(|subgroup| {
+        let is_excluded = |excluded: &Vec<String>| excluded.len() == group_path.len() + 1
+            && group_path.iter().chain([&subgroup.name])
+                .zip(excluded)
+                .all(|(name, excluded)| name_matching.eq(name, excluded));
+        Some(subgroup.uuid) != recycle_bin && !excluded_groups.iter().any(is_excluded)
+    });
+    for subgroup in &mut group.groups {
+        group_path.push(subgroup.name.clone());
+        remove_groups(subgroup, group_path, recycle_bin, excluded_groups, name_matching);
+        group_path.pop();
+    }
+}
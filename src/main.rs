@@ -2,19 +2,125 @@ extern crate ini;
 extern crate keepass;
 extern crate newline_converter;
 
-use ini::Ini;
+mod agent;
+mod attachments;
+mod databases;
+mod modifiers;
+mod options;
+mod password;
+mod pinentry;
+mod placeholders;
+mod references;
+mod secret_path;
+mod totp;
+mod url_pattern;
+
+use ini::{Ini, Properties};
+use databases::Databases;
+use options::{DuplicatePolicy, ExpiryPolicy, LookupOptions, NameMatching, PlaceholderMode};
 use keepass::{Database, DatabaseKey};
-use keepass::db::NodeRef;
+use keepass::db::{Entry, Group};
 use newline_converter::dos2unix;
+use password::{PasswordSource, PromptMethod};
+use secret_path::{EntrySelector, Field, SecretPath, Version};
+use uuid::Uuid;
 
 use std::env;
-use std::fs::File;
+use std::fs;
 use std::process;
 use std::io::{self, Write};
 
 struct KeePassConfig {
     db_path: String,
-    db_pass: String,
+    db_key: CompositeKey,
+}
+
+/// The components of a database's composite key, any non-empty combination is valid
+#[derive(Default)]
+struct CompositeKey {
+    password: Option<String>,
+    keyfile: Option<String>,
+}
+
+/// The values provided by a single configuration source
+#[derive(Default)]
+struct ConfigSource {
+    db_path: Option<String>,
+    password: Option<PasswordSource>,
+    keyfile: Option<String>,
+    prompt: Option<String>,
+    pinentry: Option<String>,
+    credential_pass: Option<String>,
+    credential_keyfile: Option<String>,
+}
+
+impl ConfigSource {
+    fn is_empty(&self) -> bool {
+        self.db_path.is_none() && !self.has_key()
+    }
+
+    /// Whether at least one key component is configured
+    fn has_key(&self) -> bool {
+        self.password.is_some() || self.keyfile.is_some() || self.prompt.is_some()
+    }
+
+    /// Merge two sources value by value, preferring the values of `self`
+    fn or(&self, other: &ConfigSource) -> ConfigSource {
+        ConfigSource {
+            db_path: self.db_path.clone().or(other.db_path.clone()),
+            password: self.password.clone().or(other.password.clone()),
+            keyfile: self.keyfile.clone().or(other.keyfile.clone()),
+            prompt: self.prompt.clone().or(other.prompt.clone()),
+            pinentry: self.pinentry.clone().or(other.pinentry.clone()),
+            credential_pass: self.credential_pass.clone().or(other.credential_pass.clone()),
+            credential_keyfile: self.credential_keyfile.clone().or(other.credential_keyfile.clone()),
+        }
+    }
+}
+
+/// systemd credentials (LoadCredential=) the password and key file may be read from
+struct Credentials {
+    directory: Option<String>,
+    pass_name: String,
+    keyfile_name: String,
+}
+
+impl Credentials {
+    /// Use the password and key file credentials that exist in $CREDENTIALS_DIRECTORY
+    fn load(&self) -> ConfigSource {
+        let directory = match &self.directory {
+            Some(d) => std::path::Path::new(d),
+            None => return ConfigSource::default(),
+        };
+        let existing = |name: &str| {
+            let path = directory.join(name);
+            path.is_file().then(|| path.to_string_lossy().to_string())
+        };
+
+        ConfigSource {
+            password: existing(&self.pass_name).map(PasswordSource::File),
+            keyfile: existing(&self.keyfile_name),
+            ..ConfigSource::default()
+        }
+    }
+}
+
+/// The database profiles of ~/.summon-keepass.ini
+struct Profiles {
+    /// The selected profile, None for the default [keepass_db] section
+    selected: Option<String>,
+    /// Names of all [db.<name>] sections
+    available: Vec<String>,
+}
+
+impl Profiles {
+    /// The INI section holding the configuration of the selected profile
+    fn section(&self) -> String {
+        match &self.selected {
+            Some(name) => format!("db.{}", name),
+            None => "keepass_db".to_string(),
+        }
+    }
 }
 
 fn main() -> std::io::Result<()> {
@@ -29,73 +135,463 @@ fn main() -> std::io::Result<()> {
     if args.len() > 1 {
         let arg = args[1].to_str().unwrap();
         if arg == "-V" || arg == "--version" {
-            out_handle.write(format!("{}\n", env!("CARGO_PKG_VERSION")).as_bytes()).unwrap();
+            out_handle.write_all(format!("{}\n", env!("CARGO_PKG_VERSION")).as_bytes()).unwrap();
             out_handle.flush().unwrap();
             process::exit(0);
         }
         if arg == "-h" || arg == "--help" {
-            out_handle.write(get_help_text().as_bytes()).unwrap();
+            out_handle.write_all(get_help_text().as_bytes()).unwrap();
             out_handle.flush().unwrap();
             process::exit(0);
         }
+        if arg == "--agent" {
+            let agent_args: Vec<String> = args[2..].iter().map(|a| a.to_string_lossy().to_string()).collect();
+            if let Err(error_msg) = agent::run(&agent_args) {
+                err_handle.write_all(error_msg.as_bytes()).unwrap();
+                err_handle.flush().unwrap();
+                process::exit(1);
+            }
+            process::exit(0);
+        }
     }
 
-    if args.len() <= 1 {
-        err_handle.write(b"no variable was provided").unwrap();
+    // Handle options preceding the secret path
+    let mut profile_flag = None;
+    let mut raw_flag = false;
+    let mut position = 1;
+    while position < args.len() {
+        let arg = args[position].to_str().unwrap();
+        if arg == "--profile" {
+            match args.get(position + 1) {
+                Some(name) => profile_flag = Some(name.to_str().unwrap().to_string()),
+                None => {
+                    err_handle.write_all(b"--profile requires a profile name").unwrap();
+                    err_handle.flush().unwrap();
+                    process::exit(1);
+                }
+            }
+            position += 2;
+        } else if let Some(name) = arg.strip_prefix("--profile=") {
+            profile_flag = Some(name.to_string());
+            position += 1;
+        } else if arg == "--raw" {
+            raw_flag = true;
+            position += 1;
+        } else {
+            break;
+        }
+    }
+    let profile = selected_profile(profile_flag);
+
+    if args.len() <= position {
+        err_handle.write_all(b"no variable was provided").unwrap();
         err_handle.flush().unwrap();
         process::exit(1);
     }
 
-    let config = match load_config() {
-        Ok(cfg) => cfg,
+    let secret_path = args[position].to_str().unwrap();
+
+    let mut databases = match Databases::new(profile) {
+        Ok(databases) => databases,
         Err(error_msg) => {
-            err_handle.write(error_msg.as_bytes()).unwrap();
+            err_handle.write_all(error_msg.as_bytes()).unwrap();
             err_handle.flush().unwrap();
             process::exit(1);
         }
     };
+    if raw_flag {
+        databases.options.placeholders = PlaceholderMode::Raw;
+    }
+
+    // Ask a running agent first, it holds the unlocked database in memory
+    let mut warnings = Vec::new();
+    let agent_result = env::var("SUMMON_KEEPASS_AGENT_SOCK").ok()
+        .and_then(|socket_path| agent::query(&socket_path, &databases, secret_path, &mut warnings));
+    if let Some(result) = agent_result {
+        exit_with_result(result, &warnings);
+    }
 
-    let keepass_db_path = &config.db_path;
-    let keepass_db_pass = &config.db_pass;
+    let result = lookup(&mut databases, secret_path, &mut warnings);
+    exit_with_result(result, &warnings);
+}
 
-    let db_path = std::path::Path::new(keepass_db_path);
-    let key = DatabaseKey::new().with_password(keepass_db_pass);
-    let db = Database::open(&mut File::open(db_path)?, key)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
-    
-    let secret_path = args[1].to_str().unwrap();
-    let secret_vec = secret_path.split("|").collect::<Vec<&str>>();
+/// A failed lookup with the message and exit code to report
+struct LookupError {
+    exit_code: i32,
+    message: String,
+}
 
-    let field;
-    if secret_vec.len() == 2 {
-        field = secret_vec[1];
-    }
-    else if secret_vec.len() == 1 {
-        field = "Password";
+impl LookupError {
+    fn new(exit_code: i32, message: String) -> LookupError {
+        LookupError { exit_code, message }
     }
-    else {
-        err_handle.write(format!("{} is no valid secret path", secret_path).as_bytes()).unwrap();
-        err_handle.flush().unwrap();
-        process::exit(2);
+}
 
+/// Write the value of a lookup to stdout or its error to stderr and exit accordingly
+/// Warnings of the lookup are written to stderr first
+fn exit_with_result(result: Result<Vec<u8>, LookupError>, warnings: &[String]) -> ! {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
     }
-    let entry_path = secret_vec[0].split("/").collect::<Vec<&str>>();
-    if let Some(NodeRef::Entry(e)) = db.root.get(&entry_path) {
-        // Check if the field exists
-        if let Some(field_value) = e.get(field) {
-            out_handle.write(dos2unix(field_value).as_bytes()).unwrap();
+    match result {
+        Ok(value) => {
+            let mut out_handle = io::stdout().lock();
+            out_handle.write_all(&value).unwrap();
             out_handle.flush().unwrap();
             process::exit(0);
-        } else {
-            err_handle.write(format!("{} could not be retrieved", secret_path).as_bytes()).unwrap();
+        }
+        Err(error) => {
+            let mut err_handle = io::stderr().lock();
+            err_handle.write_all(error.message.as_bytes()).unwrap();
             err_handle.flush().unwrap();
-            process::exit(1);
+            process::exit(error.exit_code);
         }
     }
+}
+
+/// Open and unlock a database
+/// The file contents are returned as well, to read the database again as XML
+fn open_database(db_path: &str, key: DatabaseKey) -> Result<(Database, Vec<u8>), String> {
+    let data = fs::read(db_path)
+        .map_err(|e| format!("Could not open database {}: {}", db_path, e))?;
+    let db = Database::open(&mut data.as_slice(), key)
+        .map_err(|e| format!("Could not open database {}: {}", db_path, e))?;
+    Ok((db, data))
+}
+
+/// Look up the value a secret path refers to, opening the databases it is searched in
+/// Configuration errors and databases that can not be opened are reported with exit code 1
+fn lookup(databases: &mut Databases, secret_path: &str, warnings: &mut Vec<String>) -> Result<Vec<u8>, LookupError> {
+    let (profile, path) = databases.split_prefix(secret_path);
+    let parsed = SecretPath::parse(secret_path, secret_path.len() - path.len())
+        .map_err(|e| LookupError::new(2, e))?;
+
+    // A prefix selects a single database, otherwise missing entries fall through the search order.
+    // The first database containing the entry has to provide the value.
+    let search_order = match profile {
+        Some(name) => vec![Some(name.to_string())],
+        None => databases.search_order().map_err(|e| LookupError::new(1, e))?,
+    };
+    let options = databases.options.clone();
+    let failed = |e: String| LookupError::new(1, format!("{} could not be retrieved, {}", secret_path, e));
+    for profile in &search_order {
+        let db = databases.open(profile.as_deref()).map_err(|e| LookupError::new(1, e))?;
+        let e = match find_entry(&db.root, &parsed.entry, &parsed.tags, &options, secret_path)? {
+            Some(e) => e,
+            None => continue,
+        };
+        check_expiry(e, &options, secret_path, warnings)?;
+        let (history_item, e) = select_version(e, &parsed.version).map_err(failed)?;
+        let value = match &parsed.field {
+            Field::Name(field) => {
+                let field_value = get_field(e, field, options.name_matching, secret_path)?
+                    .ok_or_else(|| failed(format!("the entry has no field '{}'", field)))?;
+                let field_value = placeholders::expand(&db.root, e, field_value, options.placeholders)
+                    .map_err(failed)?;
+                dos2unix(&field_value).as_bytes().to_vec()
+            }
+            // Attachments are returned as they are, without converting line endings
+            Field::Attachment(name) => {
+                let uuid = e.uuid;
+                databases.attachment(profile.as_deref(), uuid, history_item, name)
+                    .map_err(|e| LookupError::new(1, e))?
+                    .ok_or_else(|| failed(format!("the entry has no attachment '{}'", name)))?
+                    .to_vec()
+            }
+            Field::Totp => {
+                let totp = totp::Totp::from_entry(e).map_err(failed)?
+                    .ok_or_else(|| failed("the entry has no TOTP configuration".to_string()))?;
+                totp.code(options.now().timestamp()).into_bytes()
+            }
+        };
+        return parsed.modifiers.iter().try_fold(value, |value, modifier| modifier.apply(value))
+            .map_err(failed);
+    }
+
+    if !parsed.tags.is_empty() {
+        return Err(LookupError::new(1, format!("{} could not be retrieved, no matching entry has the tags {}",
+            secret_path, parsed.tags.join(", "))));
+    }
+    if search_order.len() > 1 {
+        let searched: Vec<String> = search_order.iter().map(|p| databases.name(p.as_deref())).collect();
+        return Err(LookupError::new(1, format!("{} could not be retrieved from databases {}",
+            secret_path, searched.join(", "))));
+    }
+    Err(LookupError::new(1, format!("{} could not be retrieved", secret_path)))
+}
+
+/// Find the entry a secret path selects, having all of `tags`
+/// Searches matching several entries are reported with exit code 2, listing the candidates
+fn find_entry<'a>(root: &'a Group, selector: &EntrySelector, tags: &[String], options: &LookupOptions,
+                  secret_path: &str) -> Result<Option<&'a Entry>, LookupError> {
+    match selector {
+        EntrySelector::Path(entry_path) => {
+            let entry_path: Vec<&str> = entry_path.iter().map(|name| name.as_str()).collect();
+            get_entry(root, &entry_path, tags, options, secret_path)
+        }
+        EntrySelector::Search(path_end) => {
+            let path_end: Vec<&str> = path_end.iter().map(|name| name.as_str()).collect();
+            let search = |name_matching: NameMatching| {
+                let mut matches = Vec::new();
+                search_entries(root, &mut Vec::new(), &|entry_path: &[&str], entry: &Entry| {
+                    ends_with(entry_path, &path_end, name_matching) && has_tags(entry, tags, name_matching)
+                }, &mut matches);
+                matches
+            };
+            let mut matches = search(NameMatching::Exact);
+            if matches.is_empty() {
+                matches = search(options.name_matching);
+            }
+            select_match(matches, options, secret_path)
+        }
+        EntrySelector::Uuid(uuid) => Ok(root.entry_by_uuid(*uuid)
+            .filter(|entry| has_tags(entry, tags, options.name_matching))),
+        EntrySelector::Url(pattern) => {
+            let mut matches = Vec::new();
+            search_entries(root, &mut Vec::new(), &|_: &[&str], entry: &Entry| {
+                entry.get_url().is_some_and(|url| url_pattern::matches(pattern, url))
+                    && has_tags(entry, tags, options.name_matching)
+            }, &mut matches);
+            select_match(matches, options, secret_path)
+        }
+    }
+}
+
+/// An entry found by a search
+struct Match<'a> {
+    /// Group names and title of the entry
+    path: Vec<&'a str>,
+    /// UUID of the group holding the entry, sibling groups may share their name
+    group: Uuid,
+    entry: &'a Entry,
+}
+
+/// Choose the entry of a search, failing with exit code 2 and the candidates if several match
+fn select_match<'a>(matches: Vec<Match<'a>>, options: &LookupOptions,
+                    secret_path: &str) -> Result<Option<&'a Entry>, LookupError> {
+    // Matches with the same title in a single group are duplicates rather than an ambiguous search
+    if matches.iter().all(|m| m.group == matches[0].group && m.path == matches[0].path) {
+        return select_duplicate(matches.into_iter().map(|m| m.entry).collect(), options.on_duplicate, secret_path);
+    }
+    let candidates: Vec<String> = matches.iter()
+        .map(|m| match matches.iter().filter(|other| other.path == m.path).count() {
+            1 => format!("  {}\n", secret_path::format_entry_path(&m.path)),
+            // Entries in sibling groups of the same name are told apart by their UUID
+            _ => format!("  {} (#{})\n", secret_path::format_entry_path(&m.path), m.entry.uuid),
+        })
+        .collect();
+    Err(LookupError::new(2, format!("{} is ambiguous, {} entries match:\n{}",
+        secret_path, matches.len(), candidates.concat())))
+}
 
-    err_handle.write(format!("{} could not be retrieved", secret_path).as_bytes()).unwrap();
-    err_handle.flush().unwrap();
-    process::exit(1);
+/// Whether an entry has all of `tags`
+fn has_tags(entry: &Entry, tags: &[String], name_matching: NameMatching) -> bool {
+    tags.iter().all(|wanted| entry.tags.iter().any(|tag| name_matching.eq(tag, wanted)))
+}
+
+/// Whether an entry path ends with the group names and title of `path_end`
+fn ends_with(entry_path: &[&str], path_end: &[&str], name_matching: NameMatching) -> bool {
+    entry_path.len() >= path_end.len()
+        && entry_path[entry_path.len() - path_end.len()..].iter()
+            .zip(path_end)
+            .all(|(name, wanted)| name_matching.eq(name, wanted))
+}
+
+/// Collect the entries below `group` for which `is_match` holds, together with their paths
+fn search_entries<'a>(group: &'a Group, group_path: &mut Vec<&'a str>, is_match: &dyn Fn(&[&str], &Entry) -> bool,
+                      matches: &mut Vec<Match<'a>>) {
+    for entry in &group.entries {
+        let title = match entry.get_title() {
+            Some(title) => title,
+            None => continue,
+        };
+        let mut entry_path = group_path.clone();
+        entry_path.push(title);
+        if is_match(&entry_path, entry) {
+            matches.push(Match { path: entry_path, group: group.uuid, entry });
+        }
+    }
+    for subgroup in &group.groups {
+        group_path.push(&subgroup.name);
+        search_entries(subgroup, group_path, is_match, matches);
+        group_path.pop();
+    }
+}
+
+/// Resolve an entry by its path of group names followed by the entry title
+/// Several entries with that title in the group are resolved by the duplicate policy,
+/// failing with exit code 3 unless configured otherwise; entries in sibling groups
+/// sharing a name or in groups and titles matching only after normalization fail with exit code 2
+fn get_entry<'a>(root: &'a Group, entry_path: &[&str], tags: &[String], options: &LookupOptions,
+                 secret_path: &str) -> Result<Option<&'a Entry>, LookupError> {
+    let (title, group_path) = match entry_path.split_last() {
+        Some(split) => split,
+        None => return Ok(None),
+    };
+
+    let mut matches = Vec::new();
+    collect_entries(root, &mut Vec::new(), group_path, title, tags, options, &mut matches);
+    select_match(matches, options, secret_path)
+}
+
+/// Collect the entries titled `title` in every group below `group` matching `group_names`
+fn collect_entries<'a>(group: &'a Group, group_path: &mut Vec<&'a str>, group_names: &[&str], title: &str,
+                       tags: &[String], options: &LookupOptions, matches: &mut Vec<Match<'a>>) {
+    let (name, rest) = match group_names.split_first() {
+        Some(split) => split,
+        None => {
+            let mut entries = options.name_matching.filter(&group.entries, |e| e.get_title(), title);
+            entries.retain(|entry| has_tags(entry, tags, options.name_matching));
+            for entry in entries {
+                // Titles differing in case or normalization are ambiguous rather than duplicates
+                let mut entry_path = group_path.clone();
+                entry_path.push(entry.get_title().unwrap_or_default());
+                matches.push(Match { path: entry_path, group: group.uuid, entry });
+            }
+            return;
+        }
+    };
+    for subgroup in options.name_matching.filter(&group.groups, |g| Some(g.name.as_str()), name) {
+        group_path.push(&subgroup.name);
+        collect_entries(subgroup, group_path, rest, title, tags, options, matches);
+        group_path.pop();
+    }
+}
+
+/// The value of an entry's field, preferring an exact match of the field name
+/// Several fields matching only after normalization are reported with exit code 2
+fn get_field<'a>(entry: &'a Entry, field: &str, name_matching: NameMatching,
+                 secret_path: &str) -> Result<Option<&'a str>, LookupError> {
+    if let Some(value) = entry.get(field) {
+        return Ok(Some(value));
+    }
+    let mut names: Vec<&String> = entry.fields.keys().filter(|name| name_matching.eq(name, field)).collect();
+    names.sort();
+    match names.as_slice() {
+        [] => Ok(None),
+        [name] => Ok(entry.get(name)),
+        _ => {
+            let candidates: Vec<String> = names.iter().map(|name| format!("  {}\n", name)).collect();
+            Err(LookupError::new(2, format!("{} is ambiguous, {} fields match '{}':\n{}",
+                secret_path, names.len(), field, candidates.concat())))
+        }
+    }
+}
+
+/// Apply the expiry policy to an entry, warning about entries expiring soon
+/// Expired entries are reported with exit code 4 if the policy denies them
+fn check_expiry(entry: &Entry, options: &LookupOptions, secret_path: &str,
+                warnings: &mut Vec<String>) -> Result<(), LookupError> {
+    let expiry = match entry.times.get_expiry().filter(|_| entry.times.expires) {
+        Some(expiry) => expiry.and_utc(),
+        None => return Ok(()),
+    };
+    let now = options.now();
+    let date = expiry.format("%Y-%m-%dT%H:%M:%SZ");
+
+    if expiry > now {
+        let days = (expiry - now).num_days();
+        if days < options.expiry_warning_days {
+            warnings.push(format!("{} expires on {}, in {} days", secret_path, date, days));
+        }
+        return Ok(());
+    }
+    match options.expired {
+        ExpiryPolicy::Allow => Ok(()),
+        ExpiryPolicy::Warn => {
+            warnings.push(format!("{} expired on {}", secret_path, date));
+            Ok(())
+        }
+        ExpiryPolicy::Deny => Err(LookupError::new(4, format!(
+            "{} could not be retrieved, the entry expired on {}", secret_path, date))),
+    }
+}
+
+/// The version of an entry a secret path selects, with the index of the history item in the database
+///
+/// History items are ordered by their modification time, the oldest one has index 0.
+fn select_version<'a>(entry: &'a Entry, version: &Version) -> Result<(Option<usize>, &'a Entry), String> {
+    let mut history: Vec<(usize, &Entry)> = entry.history.as_ref()
+        .map_or(Vec::new(), |history| history.get_entries().iter().enumerate().collect());
+    history.sort_by_key(|(_, item)| item.times.get_last_modification());
+    match version {
+        Version::Current => Ok((None, entry)),
+        Version::History(index) => {
+            let position = if *index < 0 { history.len() as i64 + index } else { *index };
+            usize::try_from(position).ok()
+                .and_then(|position| history.get(position))
+                .map(|(item, entry)| (Some(*item), *entry))
+                .ok_or_else(|| format!("the entry has no history item {} ({} stored)", index, history.len()))
+        }
+        Version::At(time) => {
+            let time = time.naive_utc();
+            let existed = |entry: &Entry| entry.times.get_last_modification().is_none_or(|modified| *modified <= time);
+            if existed(entry) {
+                return Ok((None, entry));
+            }
+            history.iter().rev()
+                .find(|(_, item)| existed(item))
+                .map(|(item, entry)| (Some(*item), *entry))
+                .ok_or_else(|| format!("the entry has no version from {}", time.and_utc().to_rfc3339()))
+        }
+    }
+}
+
+/// Choose between the entries with the same title in one group according to the duplicate policy
+fn select_duplicate<'a>(entries: Vec<&'a Entry>, on_duplicate: DuplicatePolicy,
+                        secret_path: &str) -> Result<Option<&'a Entry>, LookupError> {
+    match (entries.as_slice(), on_duplicate) {
+        ([], _) => Ok(None),
+        ([entry], _) | ([entry, ..], DuplicatePolicy::First) => Ok(Some(entry)),
+        (_, DuplicatePolicy::Newest) => Ok(entries.into_iter().max_by_key(|e| e.times.get_last_modification())),
+        (_, DuplicatePolicy::Error) => {
+            let uuids: Vec<String> = entries.iter().map(|e| format!("  {}\n", e.uuid)).collect();
+            Err(LookupError::new(3, format!(
+                "{} is ambiguous, {} entries with the same title exist in one group:\n{}\
+                 Address one of them by UUID or set on_duplicate=first|newest\n",
+                secret_path, entries.len(), uuids.concat())))
+        }
+    }
+}
+
+/// Build the database key from all configured composite key components
+fn build_database_key(composite_key: &CompositeKey) -> Result<DatabaseKey, String> {
+    let mut key = DatabaseKey::new();
+    if let Some(password) = &composite_key.password {
+        key = key.with_password(password);
+    }
+    if let Some(keyfile_path) = &composite_key.keyfile {
+        let keyfile = read_keyfile(keyfile_path)
+            .map_err(|e| format!("Could not read key file {}: {}", keyfile_path, e))?;
+        key = key.with_keyfile(&mut keyfile.as_slice())
+            .map_err(|e| format!("Could not read key file {}: {}", keyfile_path, e))?;
+    }
+    Ok(key)
+}
+
+/// Read a key file, normalizing the formats not handled by the keepass crate itself.
+///
+/// XML (v1 and v2), raw 32 byte and arbitrary files are passed through as-is and
+/// interpreted by keepass. Key files consisting of exactly 64 hexadecimal characters
+/// are decoded to their 32 byte key, matching the behavior of KeePass.
+fn read_keyfile(keyfile_path: &str) -> std::io::Result<Vec<u8>> {
+    let content = std::fs::read(keyfile_path)?;
+    let hex_key = if content.len() == 64 { decode_hex(&content) } else { None };
+    Ok(hex_key.unwrap_or(content))
+}
+
+/// Decode a string of hexadecimal characters, returning None if it is not valid hex
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    let pairs = hex.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    pairs
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok().filter(|pair| pair.chars().all(|c| c.is_ascii_hexdigit()))?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
 }
 
 /// Generate help text explaining configuration and usage
@@ -106,18 +602,97 @@ Summon provider for reading secrets from KeePass (.kdbx) database files.
 
 USAGE:
     summon-keepass [OPTIONS] <SECRET_PATH>
+    summon-keepass --agent [--socket <PATH>] [--timeout <SECONDS>] [--profile <NAME>]
     summon-keepass -h|--help
     summon-keepass -V|--version
 
 OPTIONS:
-    -h, --help       Display this help message
-    -V, --version    Display version information
+    -h, --help          Display this help message
+    -V, --version       Display version information
+    --profile <NAME>    Use the [db.<NAME>] profile of the configuration file
+    --raw               Return the value as stored, like placeholders=raw
+    --agent             Run the caching agent instead of a lookup, see AGENT
 
 SECRET PATH FORMAT:
-    [group/subgroup/]entry[|field]
+    [database:][group/subgroup/]entry[|field]
+    [database:]**/[group/]entry[|field]    (or title:[group/]entry)
+    [database:]uuid:<uuid>[|field]         (or #<uuid>)
+    [database:]url:<url>[|field]
+    [database:]tag:<tag>[+tag:<tag>][/[group/]entry][|field]
+    [database:][tags=<tag>[,<tag>]]<any of the above>
+    [database:]<entry>@history[<index>][|field]
+    [database:]<entry>@at=<RFC 3339 time>[|field]
+    [database:]<any of the above>[|field]?<modifier>[&<modifier>]
 
     By default, the 'Password' field is returned. To retrieve a different
-    field, append |field_name to the entry path.
+    field, append |field_name to the entry path. |@attachment:<name> returns
+    an attachment of the entry as it is, without converting line endings.
+    |@totp returns the current TOTP code of an entry configured for it in
+    KeePassXC (otp field or TOTP Seed and TOTP Settings).
+
+    @history[<index>] after the entry reads a stored history item instead of
+    the current version. Index 0 is the oldest item, -1 the newest one.
+    @at=<time> reads the version that was current at that time. A '@' that
+    belongs to the title can be written as %40.
+
+    Modifiers after the field transform the value before it is returned, in
+    the given order: encode=<encoding>, decode=<encoding> and trim. Supported
+    encodings are base64, base64url, hex, url and, for encoding only, json
+    (escaping for a JSON string). Without a field they follow the entry and
+    apply to the password, except for url: which needs the field. A '?' in
+    a name is written as %3F.
+
+    With **/ or title:, the entry is searched in all groups. Its path has to
+    end with the given groups and title, and exactly one entry may match.
+
+    An entry can also be addressed by its UUID, which stays the same when it
+    is renamed or moved to another group.
+
+    With url:, the entry is found by its URL field. A URL with scheme has to
+    match exactly, otherwise only the host is compared. Both may contain the
+    wildcards * and ?, and exactly one entry may match.
+
+    With tag:, the entry having all the given KeePass tags is searched in all
+    groups, optionally followed by the end of its path. A [tags=...] filter
+    restricts any other form to entries having all the tags. The lookup fails
+    if no entry or, in a search, several entries match.
+
+    If a group holds several entries with the title, the lookup fails with
+    exit code 3 and lists their UUIDs. on_duplicate=first uses the first of
+    them, on_duplicate=newest the most recently modified one.
+
+    With name_matching=normalized, group, entry and field names are compared
+    case-insensitively and after Unicode NFC normalization. Exact matches
+    are still preferred.
+
+    KeePass field references like {{REF:P@I:<uuid>}} in the value are resolved,
+    also when the referenced field contains references itself. Circular
+    references and references matching no entry fail with exit code 1.
+
+    Placeholders like {{USERNAME}}, {{TITLE}}, {{URL:HOST}} and {{S:<field>}} are
+    expanded as well, unknown ones are kept. placeholders=strict fails with
+    exit code 1 on unknown placeholders, placeholders=raw or --raw returns
+    the value as stored, without expanding placeholders and references.
+
+    Entries past their expiry time are returned by default. expired=warn
+    prints a warning to stderr, expired=deny fails with exit code 4.
+    expiry_warning_days=<N> warns about entries expiring within N days.
+
+    Entries in the recycle bin of the database are never found, unless
+    recycle_bin=include is set. exclude_groups=<group/path>,... hides further
+    groups, with their subgroups, from all lookups and searches. Both are
+    read from the section of each database's profile.
+
+    Names containing '/' or '|' can be escaped with a backslash ('\/', '\|',
+    '\\' for a backslash) or percent-encoded ('%2F', '%7C', '%25' for '%').
+
+    The optional database prefix names a [db.<name>] profile of the
+    configuration file to read the entry from, or 'default' for the selected
+    database. Each database is only opened when a secret path refers to it.
+
+    With a search order (search_order=personal,team or
+    SUMMON_KEEPASS_SEARCH_ORDER), secret paths without prefix are looked up
+    in the listed databases, using the first one containing the entry.
 
 EXAMPLES:
     summon-keepass "simple-entry"
@@ -132,11 +707,48 @@ EXAMPLES:
     summon-keepass "aws/iam/user/robot|access_key_id"
         Returns the access_key_id field from nested entry
 
+    summon-keepass "**/robot|access_key_id"
+        Returns the field from the only entry titled 'robot' in any group
+
+    summon-keepass "ssh/server|@attachment:id_ed25519"
+        Returns the attachment 'id_ed25519' of 'ssh/server'
+
+    summon-keepass "services/backup|@totp"
+        Returns the current TOTP code of 'services/backup'
+
+    summon-keepass "db/admin@history[-1]"
+        Returns the Password field from the newest history item of 'db/admin'
+
+    summon-keepass "k8s/registry|config?encode=base64"
+        Returns the field 'config' of 'k8s/registry' base64-encoded
+
+    summon-keepass "url:github.com|UserName"
+        Returns the UserName field from the entry for the host 'github.com'
+
+    summon-keepass "tag:ci+tag:aws/robot|access_key_id"
+        Returns the field from the entry 'robot' tagged with 'ci' and 'aws'
+
+    summon-keepass "team:aws/iam/user/robot|access_key_id"
+        Returns the field from the database of the 'team' profile
+
 CONFIGURATION:
 
     Option 1: Environment Variables (Recommended for projects)
         export SUMMON_KEEPASS_DB_PATH=/path/to/database.kdbx
         export SUMMON_KEEPASS_DB_PASS="your database password"
+        export SUMMON_KEEPASS_DB_PASS_CMD="pass show keepass"   (alternative)
+        export SUMMON_KEEPASS_DB_PASS_FILE=/run/secrets/keepass  (alternative)
+        export SUMMON_KEEPASS_DB_PASS_FD=3                       (alternative)
+        export SUMMON_KEEPASS_DB_PASS_PROMPT=tty|pinentry        (fallback)
+        export SUMMON_KEEPASS_PINENTRY=pinentry-gnome3           (optional)
+        export SUMMON_KEEPASS_DB_KEYFILE=/path/to/keyfile
+        export SUMMON_KEEPASS_ON_DUPLICATE=first|error|newest    (optional)
+        export SUMMON_KEEPASS_NAME_MATCHING=exact|normalized     (optional)
+        export SUMMON_KEEPASS_PLACEHOLDERS=expand|strict|raw     (optional)
+        export SUMMON_KEEPASS_EXPIRED=allow|warn|deny            (optional)
+        export SUMMON_KEEPASS_EXPIRY_WARNING_DAYS=14             (optional)
+        export SUMMON_KEEPASS_RECYCLE_BIN=exclude|include         (optional)
+        export SUMMON_KEEPASS_EXCLUDE_GROUPS=archive,team/old     (optional)
 
     Option 2: Configuration File
         Create ~/.summon-keepass.ini with:
@@ -144,105 +756,291 @@ CONFIGURATION:
         [keepass_db]
         path=/path/to/database.kdbx
         pass=your database password
+        pass_cmd=pass show keepass       (alternative)
+        pass_file=/run/secrets/keepass   (alternative)
+        pass_prompt=tty|pinentry         (fallback)
+        pinentry=pinentry-gnome3         (optional)
+        keyfile=/path/to/keyfile
+        search_order=default,team        (optional)
+        on_duplicate=first|error|newest  (optional)
+        name_matching=exact|normalized   (optional)
+        placeholders=expand|strict|raw   (optional)
+        expired=allow|warn|deny          (optional)
+        expiry_warning_days=14           (optional)
+        recycle_bin=exclude|include      (optional)
+        exclude_groups=archive,team/old  (optional)
+
+        Further databases can be configured as profiles in [db.<name>]
+        sections with the same keys, e.g. [db.prod] and [db.dev]. A profile
+        is selected with --profile or SUMMON_KEEPASS_PROFILE, otherwise
+        [keepass_db] is used. Environment variables and systemd credentials
+        only apply to the selected database, not to databases referenced by
+        a prefix in the secret path.
+
+    Option 3: systemd Credentials
+        When run by a systemd unit with LoadCredential=, the password and key
+        file are read from $CREDENTIALS_DIRECTORY/summon-keepass.pass and
+        $CREDENTIALS_DIRECTORY/summon-keepass.key. Other credential names can
+        be set via SUMMON_KEEPASS_CREDENTIAL_PASS/_KEYFILE or
+        credential_pass=/credential_keyfile=.
+
+    A password command is run through the shell and its output, without
+    trailing line breaks, is used as the password. The same applies to the
+    content of a password file or of an inherited file descriptor.
+
+    If no password source is configured, a password prompt can be enabled:
+    'tty' asks on /dev/tty with echo disabled, 'pinentry' uses the pinentry
+    program (default: pinentry). Both work although summon captures stdout.
+
+    The database key may consist of the password, the key file or both.
+    Supported key file formats are KeePass XML key files (v1 and v2), raw
+    32 byte files, 64 character hex files and arbitrary files (hashed with
+    SHA-256).
 
-    Priority: Environment variables override configuration file.
+    Priority: Environment variables override systemd credentials, which
+    override the configuration file.
     You can also mix sources (e.g., path from env, password from file).
 
+AGENT:
+    summon runs the provider once per variable, each time unlocking the
+    database again. The agent unlocks it once and keeps it in memory:
+
+        export SUMMON_KEEPASS_AGENT_SOCK=$XDG_RUNTIME_DIR/summon-keepass.sock
+        summon-keepass --agent &
+
+    While SUMMON_KEEPASS_AGENT_SOCK is set, lookups are answered by the agent,
+    falling back to the database file if it can not be reached or serves
+    another profile, database path or lookup options. The agent
+    exits after being idle for --timeout seconds (SUMMON_KEEPASS_AGENT_TIMEOUT,
+    default 900, 0 to never exit). The socket defaults to
+    $XDG_RUNTIME_DIR/summon-keepass.sock.
+
 EXIT CODES:
     0    Success
     1    Configuration error, entry not found, or field not found
-    2    Invalid secret path format
+    2    Invalid secret path format, or a search matching several entries
+    3    Several entries with the same title in one group (see on_duplicate)
+    4    Expired entry with expired=deny
 
 For more information, visit:
     https://github.com/desolat/summon-keepass
 "#, env!("CARGO_PKG_VERSION"))
 }
 
-/// Load configuration from environment variables and/or INI file
-/// Priority: Environment variables > ~/.summon-keepass.ini
-fn load_config() -> Result<KeePassConfig, String> {
+/// The profile selected by the --profile flag, falling back to SUMMON_KEEPASS_PROFILE
+///
+/// None selects the default [keepass_db] section of the configuration file.
+fn selected_profile(profile_flag: Option<String>) -> Option<String> {
+    profile_flag.or(env::var("SUMMON_KEEPASS_PROFILE").ok())
+}
+
+/// Load configuration from environment variables, systemd credentials and/or INI file
+/// Priority: Environment variables > systemd credentials > ~/.summon-keepass.ini
+///
+/// Environment variables and systemd credentials only apply to the `primary`
+/// database, other databases are configured by their profile section alone.
+fn load_config(profile: Option<&str>, primary: bool) -> Result<KeePassConfig, String> {
     // Try environment variables first
-    let env_path = env::var("SUMMON_KEEPASS_DB_PATH").ok();
-    let env_pass = env::var("SUMMON_KEEPASS_DB_PASS").ok();
+    let env_source = if primary { load_env_config() } else { ConfigSource::default() };
 
-    // Try INI file as fallback
-    let (ini_path, ini_pass) = load_ini_config();
+    // Try INI file as fallback, reading the section of the selected profile
+    let ini = load_ini_file();
+    let profiles = Profiles {
+        selected: profile.map(|p| p.to_string()),
+        available: ini.as_ref().map(list_profiles).unwrap_or_default(),
+    };
+    let ini_source = ini.as_ref()
+        .and_then(|ini| ini.section(Some(profiles.section())))
+        .map(load_ini_section)
+        .unwrap_or_default();
 
-    // Merge with priority (env vars override INI)
-    let db_path = env_path.clone().or(ini_path.clone());
-    let db_pass = env_pass.clone().or(ini_pass.clone());
+    // systemd credentials rank between both, their names may be configured in either
+    let credentials = Credentials {
+        directory: if primary { env::var("CREDENTIALS_DIRECTORY").ok() } else { None },
+        pass_name: env_source.credential_pass.clone().or(ini_source.credential_pass.clone())
+            .unwrap_or("summon-keepass.pass".to_string()),
+        keyfile_name: env_source.credential_keyfile.clone().or(ini_source.credential_keyfile.clone())
+            .unwrap_or("summon-keepass.key".to_string()),
+    };
+    let credentials_source = credentials.load();
+
+    let config_error = |failure: Option<&str>| {
+        build_config_error(&env_source, &credentials, &credentials_source, &ini_source, &profiles, failure)
+    };
 
-    // Validate both are present
-    match (db_path, db_pass) {
-        (Some(path), Some(pass)) => Ok(KeePassConfig {
-            db_path: path,
-            db_pass: pass,
-        }),
-        _ => Err(build_config_error(&env_path, &env_pass, &ini_path, &ini_pass)),
+    // An explicitly selected profile has to exist
+    if let Some(name) = profiles.selected.as_ref().filter(|name| !profiles.available.contains(name)) {
+        return Err(config_error(Some(&format!(
+            "Profile '{}' is not configured in ~/.summon-keepass.ini", name))));
     }
-}
 
-/// Load configuration from ~/.summon-keepass.ini file
-/// Returns (Option<path>, Option<password>)
-fn load_ini_config() -> (Option<String>, Option<String>) {
-    // Get HOME directory (return None if not available)
-    let home = match env::var("HOME") {
-        Ok(h) => h,
-        Err(_) => return (None, None),
+    // Merge with priority (env vars override credentials override INI)
+    let merged = env_source.or(&credentials_source).or(&ini_source);
+
+    // Validate the path and at least one key component are present
+    let db_path = match merged.db_path {
+        Some(ref path) if merged.has_key() => path.clone(),
+        _ => return Err(config_error(None)),
     };
 
-    let config_path = format!("{}/.summon-keepass.ini", home);
+    // Fall back to an interactive prompt only if no other password source is configured
+    let password_source = match (&merged.password, &merged.prompt) {
+        (Some(source), _) => Some(source.clone()),
+        (None, Some(method)) => Some(PasswordSource::Prompt(
+            PromptMethod::parse(method, merged.pinentry.as_deref())
+                .map_err(|e| config_error(Some(&e)))?,
+        )),
+        (None, None) => None,
+    };
 
-    // Try to load INI file (return None if fails)
-    let config = match Ini::load_from_file(&config_path) {
-        Ok(c) => c,
-        Err(_) => return (None, None),
+    // Resolve the password, which may involve running a command or prompting
+    let password = match &password_source {
+        Some(source) => Some(source.resolve(&db_path)
+            .map_err(|e| config_error(Some(&e)))?),
+        None => None,
     };
 
-    // Try to get section and values
-    let section = config.section(Some("keepass_db"));
-    match section {
-        Some(s) => (
-            s.get("path").map(|p| p.to_string()),
-            s.get("pass").map(|p| p.to_string()),
-        ),
-        None => (None, None),
+    Ok(KeePassConfig {
+        db_path,
+        db_key: CompositeKey {
+            password,
+            keyfile: merged.keyfile,
+        },
+    })
+}
+
+/// Load configuration from SUMMON_KEEPASS_* environment variables
+fn load_env_config() -> ConfigSource {
+    let env_password = env::var("SUMMON_KEEPASS_DB_PASS").ok().map(PasswordSource::Value)
+        .or(env::var("SUMMON_KEEPASS_DB_PASS_CMD").ok().map(PasswordSource::Command))
+        .or(env::var("SUMMON_KEEPASS_DB_PASS_FILE").ok().map(PasswordSource::File))
+        .or(env::var("SUMMON_KEEPASS_DB_PASS_FD").ok().map(PasswordSource::Fd));
+    ConfigSource {
+        db_path: env::var("SUMMON_KEEPASS_DB_PATH").ok(),
+        password: env_password,
+        keyfile: env::var("SUMMON_KEEPASS_DB_KEYFILE").ok(),
+        prompt: env::var("SUMMON_KEEPASS_DB_PASS_PROMPT").ok(),
+        pinentry: env::var("SUMMON_KEEPASS_PINENTRY").ok(),
+        credential_pass: env::var("SUMMON_KEEPASS_CREDENTIAL_PASS").ok(),
+        credential_keyfile: env::var("SUMMON_KEEPASS_CREDENTIAL_KEYFILE").ok(),
+    }
+}
+
+/// Load the ~/.summon-keepass.ini file, None if it does not exist or is invalid
+fn load_ini_file() -> Option<Ini> {
+    let home = env::var("HOME").ok()?;
+    let config_path = format!("{}/.summon-keepass.ini", home);
+    Ini::load_from_file(&config_path).ok()
+}
+
+/// Names of the profiles configured as [db.<name>] sections
+fn list_profiles(ini: &Ini) -> Vec<String> {
+    ini.sections()
+        .flatten()
+        .filter_map(|section| section.strip_prefix("db."))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Read the database configuration of a [keepass_db] or [db.<name>] section
+fn load_ini_section(s: &Properties) -> ConfigSource {
+    ConfigSource {
+        db_path: s.get("path").map(|p| p.to_string()),
+        password: s.get("pass").map(|p| PasswordSource::Value(p.to_string()))
+            .or(s.get("pass_cmd").map(|p| PasswordSource::Command(p.to_string())))
+            .or(s.get("pass_file").map(|p| PasswordSource::File(p.to_string()))),
+        keyfile: s.get("keyfile").map(|p| p.to_string()),
+        prompt: s.get("pass_prompt").map(|p| p.to_string()),
+        pinentry: s.get("pinentry").map(|p| p.to_string()),
+        credential_pass: s.get("credential_pass").map(|p| p.to_string()),
+        credential_keyfile: s.get("credential_keyfile").map(|p| p.to_string()),
     }
 }
 
 /// Build a helpful error message showing what configuration sources were checked
+/// `failure` describes a source that was found but could not be used
 fn build_config_error(
-    env_path: &Option<String>,
-    env_pass: &Option<String>,
-    ini_path: &Option<String>,
-    ini_pass: &Option<String>,
+    env_source: &ConfigSource,
+    credentials: &Credentials,
+    credentials_source: &ConfigSource,
+    ini_source: &ConfigSource,
+    profiles: &Profiles,
+    failure: Option<&str>,
 ) -> String {
-    let mut msg = String::from("Configuration error: Could not load KeePass database configuration.\n\n");
+    let mut msg = match &profiles.selected {
+        Some(name) => format!("Configuration error: Could not load KeePass database configuration of profile '{}'.\n\n", name),
+        None => String::from("Configuration error: Could not load KeePass database configuration.\n\n"),
+    };
+
+    if let Some(failure) = failure {
+        msg.push_str(&format!("{}\n\n", failure));
+    }
 
     msg.push_str("Checked sources:\n");
 
     // Environment variables
     msg.push_str("  Environment variables:\n");
     msg.push_str(&format!("    SUMMON_KEEPASS_DB_PATH: {}\n",
-        if env_path.is_some() { "✓ Found" } else { "✗ Not set" }));
+        if env_source.db_path.is_some() { "✓ Found" } else { "✗ Not set" }));
     msg.push_str(&format!("    SUMMON_KEEPASS_DB_PASS: {}\n",
-        if env_pass.is_some() { "✓ Found" } else { "✗ Not set" }));
+        if matches!(env_source.password, Some(PasswordSource::Value(_))) { "✓ Found" } else { "✗ Not set" }));
+    msg.push_str(&format!("    SUMMON_KEEPASS_DB_PASS_CMD: {}\n",
+        if matches!(env_source.password, Some(PasswordSource::Command(_))) { "✓ Found" } else { "✗ Not set" }));
+    msg.push_str(&format!("    SUMMON_KEEPASS_DB_PASS_FILE: {}\n",
+        if matches!(env_source.password, Some(PasswordSource::File(_))) { "✓ Found" } else { "✗ Not set" }));
+    msg.push_str(&format!("    SUMMON_KEEPASS_DB_PASS_FD: {}\n",
+        if matches!(env_source.password, Some(PasswordSource::Fd(_))) { "✓ Found" } else { "✗ Not set" }));
+    msg.push_str(&format!("    SUMMON_KEEPASS_DB_PASS_PROMPT: {}\n",
+        if env_source.prompt.is_some() { "✓ Found" } else { "✗ Not set" }));
+    msg.push_str(&format!("    SUMMON_KEEPASS_DB_KEYFILE: {}\n",
+        if env_source.keyfile.is_some() { "✓ Found" } else { "✗ Not set" }));
+
+    // systemd credentials
+    msg.push_str("  systemd credentials ($CREDENTIALS_DIRECTORY):\n");
+    if credentials.directory.is_some() {
+        msg.push_str(&format!("    {}: {}\n", credentials.pass_name,
+            if credentials_source.password.is_some() { "✓ Found" } else { "✗ Missing" }));
+        msg.push_str(&format!("    {}: {}\n", credentials.keyfile_name,
+            if credentials_source.keyfile.is_some() { "✓ Found" } else { "✗ Missing" }));
+    } else {
+        msg.push_str("    ✗ CREDENTIALS_DIRECTORY not set\n");
+    }
 
     // INI file
-    msg.push_str("  Configuration file (~/.summon-keepass.ini):\n");
-    if ini_path.is_some() || ini_pass.is_some() {
+    msg.push_str(&format!("  Configuration file (~/.summon-keepass.ini), section [{}]:\n", profiles.section()));
+    if !ini_source.is_empty() {
         msg.push_str(&format!("    path: {}\n",
-            if ini_path.is_some() { "✓ Found" } else { "✗ Missing" }));
+            if ini_source.db_path.is_some() { "✓ Found" } else { "✗ Missing" }));
         msg.push_str(&format!("    pass: {}\n",
-            if ini_pass.is_some() { "✓ Found" } else { "✗ Missing" }));
+            if matches!(ini_source.password, Some(PasswordSource::Value(_))) { "✓ Found" } else { "✗ Missing" }));
+        msg.push_str(&format!("    pass_cmd: {}\n",
+            if matches!(ini_source.password, Some(PasswordSource::Command(_))) { "✓ Found" } else { "✗ Missing" }));
+        msg.push_str(&format!("    pass_file: {}\n",
+            if matches!(ini_source.password, Some(PasswordSource::File(_))) { "✓ Found" } else { "✗ Missing" }));
+        msg.push_str(&format!("    pass_prompt: {}\n",
+            if ini_source.prompt.is_some() { "✓ Found" } else { "✗ Missing" }));
+        msg.push_str(&format!("    keyfile: {}\n",
+            if ini_source.keyfile.is_some() { "✓ Found" } else { "✗ Missing" }));
     } else {
         msg.push_str("    ✗ File not found or invalid format\n");
     }
 
-    msg.push_str("\nRequired: Both database path and password must be configured.\n");
+    // Profiles
+    if profiles.available.is_empty() {
+        msg.push_str("\nAvailable profiles: none ([keepass_db] is used unless a [db.<name>] profile is selected)\n");
+    } else {
+        msg.push_str(&format!("\nAvailable profiles: {} (select with --profile or SUMMON_KEEPASS_PROFILE)\n",
+            profiles.available.join(", ")));
+    }
+
+    msg.push_str("\nRequired: The database path and at least one key component must be configured.\n");
+    msg.push_str("Valid key combinations are:\n");
+    msg.push_str("  - Password only (SUMMON_KEEPASS_DB_PASS[_CMD|_FILE|_FD|_PROMPT] or pass=/pass_cmd=/pass_file=/pass_prompt=)\n");
+    msg.push_str("  - Key file only (SUMMON_KEEPASS_DB_KEYFILE or keyfile=)\n");
+    msg.push_str("  - Password and key file\n");
     msg.push_str("Set either:\n");
-    msg.push_str("  - Environment variables: SUMMON_KEEPASS_DB_PATH and SUMMON_KEEPASS_DB_PASS\n");
-    msg.push_str("  - Or create ~/.summon-keepass.ini with [keepass_db] section\n");
+    msg.push_str("  - Environment variables: SUMMON_KEEPASS_DB_PATH plus the key components\n");
+    msg.push_str("  - Or create ~/.summon-keepass.ini with [keepass_db] section or [db.<name>] profiles\n");
 
     msg
 }
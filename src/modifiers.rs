@@ -0,0 +1,150 @@
+use base64::Engine;
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+
+use crate::decode_hex;
+
+/// Base64 with padding when encoding, padding is optional when decoding
+const BASE64: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent));
+/// URL-safe base64 without padding when encoding, as used by JWTs
+const BASE64_URL: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_encode_padding(false).with_decode_padding_mode(DecodePaddingMode::Indifferent));
+
+/// An encoding applied by `encode=`
+#[derive(Clone, Copy)]
+pub enum Encoding {
+    Base64,
+    Base64Url,
+    Hex,
+    /// Percent-encoding of everything but the unreserved characters of RFC 3986
+    Url,
+    /// Escaping for the content of a JSON string, without the quotes
+    Json,
+}
+
+/// An encoding reverted by `decode=`, JSON escaping is only supported for encoding
+#[derive(Clone, Copy)]
+pub enum Decoding {
+    Base64,
+    Base64Url,
+    Hex,
+    /// Percent-encoding, keeping invalid sequences as they are
+    Url,
+}
+
+/// A transformation of the returned value, `?<modifier>[&<modifier>...]` after the field
+#[derive(Clone, Copy)]
+pub enum Modifier {
+    Encode(Encoding),
+    Decode(Decoding),
+    /// Remove leading and trailing whitespace
+    Trim,
+}
+
+impl Modifier {
+    /// Parse a modifier `trim`, `encode=<encoding>` or `decode=<encoding>`
+    pub fn parse(modifier: &str) -> Result<Modifier, String> {
+        let (name, encoding) = match modifier.split_once('=') {
+            Some((name, encoding)) => (name, Some(encoding)),
+            None => (modifier, None),
+        };
+        let unknown = |encoding: &str, expected: &str| format!("unknown encoding '{}' (expected {})", encoding, expected);
+        match (name, encoding) {
+            ("trim", None) => Ok(Modifier::Trim),
+            ("encode" | "decode", None) => Err(format!("modifier '{}' requires an encoding", name)),
+            ("encode", Some(encoding)) => match encoding {
+                "base64" => Ok(Modifier::Encode(Encoding::Base64)),
+                "base64url" => Ok(Modifier::Encode(Encoding::Base64Url)),
+                "hex" => Ok(Modifier::Encode(Encoding::Hex)),
+                "url" => Ok(Modifier::Encode(Encoding::Url)),
+                "json" => Ok(Modifier::Encode(Encoding::Json)),
+                _ => Err(unknown(encoding, "'base64', 'base64url', 'hex', 'url' or 'json'")),
+            },
+            ("decode", Some(encoding)) => match encoding {
+                "base64" => Ok(Modifier::Decode(Decoding::Base64)),
+                "base64url" => Ok(Modifier::Decode(Decoding::Base64Url)),
+                "hex" => Ok(Modifier::Decode(Decoding::Hex)),
+                "url" => Ok(Modifier::Decode(Decoding::Url)),
+                _ => Err(unknown(encoding, "'base64', 'base64url', 'hex' or 'url'")),
+            },
+            _ => Err(format!("unknown modifier '{}' (expected 'encode=<encoding>', 'decode=<encoding>' or 'trim')",
+                modifier)),
+        }
+    }
+
+    /// Transform a value
+    ///
+    /// Whitespace in base64 and hex values, like line breaks of wrapped base64, is
+    /// ignored when decoding them.
+    pub fn apply(self, value: Vec<u8>) -> Result<Vec<u8>, String> {
+        let without_whitespace = || -> Vec<u8> { value.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect() };
+        match self {
+            Modifier::Trim => Ok(value.trim_ascii().to_vec()),
+            Modifier::Encode(Encoding::Base64) => Ok(BASE64.encode(&value).into_bytes()),
+            Modifier::Encode(Encoding::Base64Url) => Ok(BASE64_URL.encode(&value).into_bytes()),
+            Modifier::Encode(Encoding::Hex) => Ok(value.iter().map(|b| format!("{:02x}", b)).collect::<String>().into_bytes()),
+            Modifier::Encode(Encoding::Url) => Ok(percent_encode(&value).into_bytes()),
+            Modifier::Encode(Encoding::Json) => json_escape(&value),
+            Modifier::Decode(Decoding::Base64) => BASE64.decode(without_whitespace())
+                .map_err(|_| "the value is no valid base64".to_string()),
+            Modifier::Decode(Decoding::Base64Url) => BASE64_URL.decode(without_whitespace())
+                .map_err(|_| "the value is no valid base64url".to_string()),
+            Modifier::Decode(Decoding::Hex) => decode_hex(&without_whitespace())
+                .ok_or_else(|| "the value is no valid hex".to_string()),
+            Modifier::Decode(Decoding::Url) => Ok(percent_decode(&value)),
+        }
+    }
+}
+
+/// Percent-encode all bytes but the unreserved characters of RFC 3986
+fn percent_encode(value: &[u8]) -> String {
+    value.iter()
+        .map(|&b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Decode the %XX sequences of a value, keeping invalid ones as they are
+pub fn percent_decode(value: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut rest = value;
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = tail.get(..2)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .filter(|_| byte == b'%');
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    bytes
+}
+
+/// Escape a value for the content of a JSON string, which has to be valid UTF-8
+fn json_escape(value: &[u8]) -> Result<Vec<u8>, String> {
+    let value = std::str::from_utf8(value).map_err(|_| "the value is no valid UTF-8 and can not be escaped for JSON")?;
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c < ' ' => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    Ok(escaped.into_bytes())
+}
@@ -0,0 +1,203 @@
+use std::env;
+
+use chrono::{DateTime, Utc};
+use ini::Ini;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::secret_path::parse_group_path;
+
+/// How a lookup chooses between several entries with the same title in one group
+#[derive(Clone, Copy, Debug)]
+pub enum DuplicatePolicy {
+    /// Use the first entry, as KeePass lists them
+    First,
+    /// Fail, listing the UUIDs of the entries
+    Error,
+    /// Use the most recently modified entry
+    Newest,
+}
+
+/// How group, entry and field names of a secret path are compared
+#[derive(Clone, Copy, Debug)]
+pub enum NameMatching {
+    /// Names have to be equal
+    Exact,
+    /// Names are compared case-insensitively after NFC normalization, exact matches are preferred
+    Normalized,
+}
+
+impl NameMatching {
+    /// The items named `wanted`, preferring exact matches over normalized ones
+    pub fn filter<'a, T>(self, items: &'a [T], name: impl Fn(&T) -> Option<&str>, wanted: &str) -> Vec<&'a T> {
+        let exact: Vec<&T> = items.iter().filter(|item| name(item) == Some(wanted)).collect();
+        match self {
+            NameMatching::Normalized if exact.is_empty() => {
+                items.iter().filter(|item| name(item).is_some_and(|n| self.eq(n, wanted))).collect()
+            }
+            _ => exact,
+        }
+    }
+
+    /// Whether two names are equal according to this matching
+    pub fn eq(self, name: &str, wanted: &str) -> bool {
+        match self {
+            NameMatching::Exact => name == wanted,
+            NameMatching::Normalized => name == wanted || normalize(name) == normalize(wanted),
+        }
+    }
+}
+
+/// Lowercase and NFC normalize a name
+fn normalize(name: &str) -> String {
+    name.to_lowercase().nfc().collect()
+}
+
+/// How placeholders like `{USERNAME}` in returned values are handled
+#[derive(Clone, Copy, Debug)]
+pub enum PlaceholderMode {
+    /// Expand known placeholders and keep unknown ones
+    Expand,
+    /// Expand known placeholders and fail on unknown ones
+    Strict,
+    /// Return values as they are stored, without resolving placeholders and field references
+    Raw,
+}
+
+/// How a lookup treats entries whose expiry time has passed
+#[derive(Clone, Copy, Debug)]
+pub enum ExpiryPolicy {
+    /// Return the value as if the entry had not expired
+    Allow,
+    /// Return the value with a warning on stderr
+    Warn,
+    /// Fail with exit code 4
+    Deny,
+}
+
+/// Settings of how secret paths are looked up
+///
+/// Each setting is read from its SUMMON_KEEPASS_* environment variable or, if not
+/// set, from the configuration file section of the selected profile.
+#[derive(Clone, Debug)]
+pub struct LookupOptions {
+    /// Names of the databases searched for secret paths without prefix
+    pub search_order: Option<Vec<String>>,
+    pub on_duplicate: DuplicatePolicy,
+    pub name_matching: NameMatching,
+    pub placeholders: PlaceholderMode,
+    pub expired: ExpiryPolicy,
+    /// Days before the expiry of an entry from which lookups warn about it, 0 for no warning
+    pub expiry_warning_days: i64,
+    /// Time of the lookup from SUMMON_KEEPASS_NOW, None for the current time
+    pub now: Option<DateTime<Utc>>,
+}
+
+impl LookupOptions {
+    pub fn load(ini: Option<&Ini>, section: &str) -> Result<LookupOptions, String> {
+        let setting = |env_name: &str, key: &str| {
+            env::var(env_name).ok()
+                .or(ini.and_then(|ini| ini.get_from(Some(section), key)).map(|value| value.to_string()))
+        };
+
+        let search_order = setting("SUMMON_KEEPASS_SEARCH_ORDER", "search_order")
+            .map(|order| order.split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect());
+
+        let on_duplicate = match setting("SUMMON_KEEPASS_ON_DUPLICATE", "on_duplicate").as_deref() {
+            None | Some("error") => DuplicatePolicy::Error,
+            Some("first") => DuplicatePolicy::First,
+            Some("newest") => DuplicatePolicy::Newest,
+            Some(other) => return Err(format!(
+                "Unknown duplicate policy '{}', expected 'first', 'error' or 'newest'", other)),
+        };
+
+        let name_matching = match setting("SUMMON_KEEPASS_NAME_MATCHING", "name_matching").as_deref() {
+            None | Some("exact") => NameMatching::Exact,
+            Some("normalized") => NameMatching::Normalized,
+            Some(other) => return Err(format!(
+                "Unknown name matching '{}', expected 'exact' or 'normalized'", other)),
+        };
+
+        let placeholders = match setting("SUMMON_KEEPASS_PLACEHOLDERS", "placeholders").as_deref() {
+            None | Some("expand") => PlaceholderMode::Expand,
+            Some("strict") => PlaceholderMode::Strict,
+            Some("raw") => PlaceholderMode::Raw,
+            Some(other) => return Err(format!(
+                "Unknown placeholder mode '{}', expected 'expand', 'strict' or 'raw'", other)),
+        };
+
+        let expired = match setting("SUMMON_KEEPASS_EXPIRED", "expired").as_deref() {
+            None | Some("allow") => ExpiryPolicy::Allow,
+            Some("warn") => ExpiryPolicy::Warn,
+            Some("deny") => ExpiryPolicy::Deny,
+            Some(other) => return Err(format!(
+                "Unknown expiry policy '{}', expected 'allow', 'warn' or 'deny'", other)),
+        };
+
+        let expiry_warning_days = match setting("SUMMON_KEEPASS_EXPIRY_WARNING_DAYS", "expiry_warning_days") {
+            None => 0,
+            Some(days) => days.trim().parse::<u32>()
+                .map_err(|_| format!("Invalid expiry warning '{}', expected a number of days", days))?.into(),
+        };
+
+        // Only read from the environment, to test expiry and TOTP codes at a fixed time
+        let now = match env::var("SUMMON_KEEPASS_NOW") {
+            Ok(now) => Some(DateTime::parse_from_rfc3339(&now).map(|time| time.to_utc()).ok()
+                .or_else(|| now.parse().ok().and_then(|seconds| DateTime::from_timestamp(seconds, 0)))
+                .ok_or_else(|| format!("Invalid SUMMON_KEEPASS_NOW '{}', expected RFC 3339 or seconds since the epoch", now))?),
+            Err(_) => None,
+        };
+
+        Ok(LookupOptions {
+            search_order, on_duplicate, name_matching, placeholders, expired, expiry_warning_days, now,
+        })
+    }
+
+    /// The current time, unless overridden by SUMMON_KEEPASS_NOW in RFC 3339 format or as seconds since the epoch
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now.unwrap_or_else(Utc::now)
+    }
+}
+
+/// The groups of a database hidden from all lookups
+///
+/// Unlike the other settings they belong to a database: the primary one reads its
+/// SUMMON_KEEPASS_* environment variable or, if not set, the section of the selected
+/// profile, other databases their [db.<name>] section only.
+#[derive(Clone, Debug)]
+pub struct GroupFilter {
+    /// Whether the recycle bin of the database is searched as well
+    pub include_recycle_bin: bool,
+    /// Paths of groups ignored by all lookups, as group names
+    pub excluded_groups: Vec<Vec<String>>,
+}
+
+impl GroupFilter {
+    pub fn load(ini: Option<&Ini>, section: &str, primary: bool) -> Result<GroupFilter, String> {
+        let setting = |env_name: &str, key: &str| {
+            env::var(env_name).ok().filter(|_| primary)
+                .or(ini.and_then(|ini| ini.get_from(Some(section), key)).map(|value| value.to_string()))
+        };
+
+        let include_recycle_bin = match setting("SUMMON_KEEPASS_RECYCLE_BIN", "recycle_bin").as_deref() {
+            None | Some("exclude") => false,
+            Some("include") => true,
+            Some(other) => return Err(format!(
+                "Unknown recycle bin handling '{}', expected 'exclude' or 'include'", other)),
+        };
+
+        // Names are escaped as in secret paths, a ',' in a name has to be written as '%2C'
+        let mut excluded_groups = Vec::new();
+        for path in setting("SUMMON_KEEPASS_EXCLUDE_GROUPS", "exclude_groups").iter().flat_map(|groups| groups.split(',')) {
+            let group_path = parse_group_path(path)
+                .map_err(|e| format!("Invalid excluded group '{}', {}", path.trim(), e))?;
+            if !group_path.is_empty() {
+                excluded_groups.push(group_path);
+            }
+        }
+
+        Ok(GroupFilter { include_recycle_bin, excluded_groups })
+    }
+}
@@ -0,0 +1,118 @@
+use std::fs;
+use std::process::{Command, Stdio};
+
+use crate::pinentry;
+
+/// Where the database password is read from
+#[derive(Clone)]
+pub enum PasswordSource {
+    /// The password itself (SUMMON_KEEPASS_DB_PASS / pass=)
+    Value(String),
+    /// A shell command printing the password on stdout (SUMMON_KEEPASS_DB_PASS_CMD / pass_cmd=)
+    Command(String),
+    /// A file containing the password (SUMMON_KEEPASS_DB_PASS_FILE / pass_file=)
+    File(String),
+    /// An inherited file descriptor to read the password from (SUMMON_KEEPASS_DB_PASS_FD)
+    Fd(String),
+    /// Ask interactively, only used if no other password source is configured
+    /// (SUMMON_KEEPASS_DB_PASS_PROMPT / pass_prompt=)
+    Prompt(PromptMethod),
+}
+
+/// How to interactively ask for the password
+#[derive(Clone)]
+pub enum PromptMethod {
+    /// Read from /dev/tty with echo disabled
+    Tty,
+    /// Ask the given pinentry program via the Assuan protocol
+    Pinentry(String),
+}
+
+impl PromptMethod {
+    /// Parse a configured prompt method, `pinentry` being the program to use for `pinentry`
+    pub fn parse(method: &str, pinentry: Option<&str>) -> Result<PromptMethod, String> {
+        match method {
+            "tty" => Ok(PromptMethod::Tty),
+            "pinentry" => Ok(PromptMethod::Pinentry(pinentry.unwrap_or("pinentry").to_string())),
+            _ => Err(format!("Unknown password prompt '{}', expected 'tty' or 'pinentry'", method)),
+        }
+    }
+}
+
+impl PasswordSource {
+    /// Resolve the source to the actual password of the database at `db_path`
+    pub fn resolve(&self, db_path: &str) -> Result<String, String> {
+        match self {
+            PasswordSource::Value(password) => Ok(password.clone()),
+            PasswordSource::Command(command) => run_password_command(command),
+            PasswordSource::File(path) => read_password_file(path),
+            PasswordSource::Fd(fd) => read_password_fd(fd),
+            PasswordSource::Prompt(PromptMethod::Tty) => {
+                rpassword::prompt_password(format!("Password for {}: ", db_path))
+                    .map_err(|e| format!("Password prompt on /dev/tty failed: {}", e))
+            }
+            PasswordSource::Prompt(PromptMethod::Pinentry(program)) => {
+                pinentry::get_pin(program, &format!("Enter the password for the KeePass database {}", db_path))
+            }
+        }
+    }
+}
+
+/// Run a password command through the shell and return its stdout without trailing line breaks
+///
+/// stdin is inherited so commands like `pass` or `gpg` can still ask for a passphrase,
+/// stderr is captured to explain a failing command in the configuration error.
+fn run_password_command(command: &str) -> Result<String, String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Password command `{}` could not be run: {}", command, e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim();
+        return Err(format!("Password command `{}` failed with {}: {}",
+            command,
+            output.status,
+            if stderr.is_empty() { "(no output on stderr)" } else { stderr }));
+    }
+
+    let stdout = String::from_utf8(output.stdout)
+        .map_err(|_| format!("Password command `{}` did not print valid UTF-8", command))?;
+    Ok(stdout.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Read the password from a file, warning if other users may read it as well
+fn read_password_file(path: &str) -> Result<String, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Password file {} could not be read: {}", path, e))?;
+    warn_if_readable_by_others(path);
+    Ok(content.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Read the password from an inherited file descriptor, e.g. `3<secret` in the shell
+fn read_password_fd(fd: &str) -> Result<String, String> {
+    let fd: u32 = fd.trim().parse()
+        .map_err(|_| format!("Password file descriptor {} is not a valid number", fd))?;
+    let content = fs::read_to_string(format!("/dev/fd/{}", fd))
+        .map_err(|e| format!("Password file descriptor {} could not be read: {}", fd, e))?;
+    Ok(content.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(unix)]
+fn warn_if_readable_by_others(path: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Ok(metadata) = fs::metadata(path) {
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            eprintln!("Warning: password file {} is accessible by group or others (mode {:o}), consider `chmod 600 {}`",
+                path, mode, path);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_readable_by_others(_path: &str) {}
@@ -0,0 +1,112 @@
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// Ask for the password via a pinentry program speaking the Assuan protocol
+///
+/// pinentry draws its own dialog (GUI or curses on the terminal), so this works even
+/// though summon captures the stdout of its provider.
+pub fn get_pin(program: &str, description: &str) -> Result<String, String> {
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Pinentry program `{}` could not be run: {}", program, e))?;
+
+    let result = run_session(&mut child, description);
+    let _ = child.kill();
+    let _ = child.wait();
+    result.map_err(|e| format!("Pinentry program `{}` failed: {}", program, e))
+}
+
+/// Run the Assuan session: greeting, options, GETPIN
+fn run_session(child: &mut Child, description: &str) -> Result<String, String> {
+    let mut stdin = child.stdin.take().ok_or("stdin not available")?;
+    let mut stdout = BufReader::new(child.stdout.take().ok_or("stdout not available")?);
+
+    // The server greets with OK once it is ready
+    read_response(&mut stdout)?;
+
+    if let Ok(tty) = env::var("GPG_TTY") {
+        command(&mut stdin, &mut stdout, &format!("OPTION ttyname={}", escape(&tty)))?;
+    }
+    if let Ok(term) = env::var("TERM") {
+        command(&mut stdin, &mut stdout, &format!("OPTION ttytype={}", escape(&term)))?;
+    }
+    command(&mut stdin, &mut stdout, "SETTITLE summon-keepass")?;
+    command(&mut stdin, &mut stdout, &format!("SETDESC {}", escape(description)))?;
+    command(&mut stdin, &mut stdout, "SETPROMPT Password:")?;
+
+    let pin = command(&mut stdin, &mut stdout, "GETPIN")?;
+    let _ = writeln!(stdin, "BYE");
+
+    Ok(pin)
+}
+
+/// Send a single command and return the data lines of its response
+fn command(stdin: &mut ChildStdin, stdout: &mut BufReader<ChildStdout>, line: &str) -> Result<String, String> {
+    writeln!(stdin, "{}", line)
+        .and_then(|_| stdin.flush())
+        .map_err(|e| e.to_string())?;
+    read_response(stdout)
+}
+
+/// Read response lines until OK or ERR, collecting the decoded data of `D` lines
+fn read_response(stdout: &mut BufReader<ChildStdout>) -> Result<String, String> {
+    let mut data = String::new();
+    loop {
+        let mut line = String::new();
+        let read = stdout.read_line(&mut line).map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Err("unexpected end of the Assuan session".to_string());
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        if line == "OK" || line.starts_with("OK ") {
+            return Ok(data);
+        } else if let Some(error) = line.strip_prefix("ERR ") {
+            // e.g. "ERR 83886179 Operation cancelled <Pinentry>"
+            let message = error.split_once(' ').map(|(_, m)| m).unwrap_or(error);
+            return Err(message.to_string());
+        } else if let Some(chunk) = line.strip_prefix("D ") {
+            data.push_str(&unescape(chunk));
+        }
+        // Status (S) and comment (#) lines are ignored
+    }
+}
+
+/// Percent-escape the characters not allowed verbatim in an Assuan line
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '%' => escaped.push_str("%25"),
+            '\n' => escaped.push_str("%0A"),
+            '\r' => escaped.push_str("%0D"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Decode the percent-escapes of an Assuan data line
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped_byte = bytes.get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(byte) = escaped_byte {
+            decoded.push(byte);
+            i += 3;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
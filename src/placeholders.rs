@@ -0,0 +1,161 @@
+use keepass::db::{Entry, Group};
+use uuid::Uuid;
+
+use crate::options::PlaceholderMode;
+use crate::references::Reference;
+use crate::url_pattern;
+
+/// Standard fields of an entry by their placeholder
+const FIELDS: [(&str, &str); 5] = [
+    ("TITLE", "Title"), ("USERNAME", "UserName"), ("PASSWORD", "Password"), ("URL", "URL"), ("NOTES", "Notes"),
+];
+
+/// Expand the KeePass placeholders and field references in a value of `entry`
+///
+/// Supported are the standard fields (`{USERNAME}`, ...), `{UUID}`, custom fields
+/// (`{S:<name>}`), parts of the URL (`{URL:HOST}`, ...) and field references
+/// (`{REF:...}`). Placeholders are expanded recursively, unknown ones are kept unless
+/// `mode` is strict. Placeholder names are case-insensitive.
+pub fn expand(root: &Group, entry: &Entry, value: &str, mode: PlaceholderMode) -> Result<String, String> {
+    match mode {
+        PlaceholderMode::Raw => Ok(value.to_string()),
+        PlaceholderMode::Expand | PlaceholderMode::Strict => {
+            let mut expander = Expander {
+                root,
+                strict: matches!(mode, PlaceholderMode::Strict),
+                resolving: Vec::new(),
+            };
+            expander.expand(entry, value)
+        }
+    }
+}
+
+struct Expander<'a> {
+    root: &'a Group,
+    strict: bool,
+    /// The fields being expanded by entry, to detect circular references
+    resolving: Vec<(Uuid, String)>,
+}
+
+impl Expander<'_> {
+    fn expand(&mut self, entry: &Entry, value: &str) -> Result<String, String> {
+        let mut expanded = String::new();
+        let mut rest = value;
+        while let Some(start) = rest.find('{') {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let token = match rest.find('}') {
+                Some(end) => &rest[..end + 1],
+                None => break,
+            };
+
+            // Braces around anything but a placeholder name are kept as they are
+            let name = &token[1..token.len() - 1];
+            if !is_placeholder(name) {
+                expanded.push('{');
+                rest = &rest[1..];
+                continue;
+            }
+            match self.placeholder(entry, token, name)? {
+                Some(value) => expanded.push_str(&value),
+                None if self.strict => return Err(format!("unknown placeholder {}", token)),
+                None => expanded.push_str(token),
+            }
+            rest = &rest[token.len()..];
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    /// The value of a placeholder, None if it is unknown
+    fn placeholder(&mut self, entry: &Entry, token: &str, name: &str) -> Result<Option<String>, String> {
+        let (key, argument) = match name.split_once(':') {
+            Some((key, argument)) => (key.to_ascii_uppercase(), Some(argument)),
+            None => (name.to_ascii_uppercase(), None),
+        };
+        match (key.as_str(), argument) {
+            ("REF", Some(_)) => {
+                let reference = match Reference::parse(token) {
+                    Some(reference) => reference,
+                    None => return Ok(None),
+                };
+                let target = reference.find(self.root)
+                    .ok_or_else(|| format!("field reference {} matches no entry", token))?;
+                match reference.wanted_field() {
+                    Some(field) => self.field(target, field, token).map(Some),
+                    None => Ok(Some(uuid(target))),
+                }
+            }
+            ("S", Some(field)) if entry.fields.contains_key(field) => self.field(entry, field, token).map(Some),
+            ("URL", Some(part)) => {
+                let url = self.field(entry, "URL", token)?;
+                Ok(url_part(&url, part))
+            }
+            ("UUID", None) => Ok(Some(uuid(entry))),
+            (key, None) => match FIELDS.iter().find(|(placeholder, _)| *placeholder == key) {
+                Some((_, field)) => self.field(entry, field, token).map(Some),
+                None => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// The expanded value of a field, empty if the entry does not have it
+    fn field(&mut self, entry: &Entry, field: &str, token: &str) -> Result<String, String> {
+        let key = (entry.uuid, field.to_string());
+        if self.resolving.contains(&key) {
+            return Err(format!("circular field reference {}", token));
+        }
+        self.resolving.push(key);
+        let value = self.expand(entry, entry.get(field).unwrap_or_default());
+        self.resolving.pop();
+        value
+    }
+}
+
+/// Whether the text between braces has the form of a placeholder, `NAME` or `NAME:argument`
+fn is_placeholder(name: &str) -> bool {
+    let key = name.split(':').next().unwrap_or_default();
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The UUID of an entry as KeePass shows it
+fn uuid(entry: &Entry) -> String {
+    entry.uuid.simple().to_string().to_uppercase()
+}
+
+/// A part of a URL as named by the `{URL:<part>}` placeholders, None for unknown parts
+fn url_part(url: &str, part: &str) -> Option<String> {
+    let (scheme, without_scheme) = url.split_once("://").unwrap_or(("", url));
+    let (authority, path_query) = without_scheme.split_at(without_scheme.find(['/', '?', '#']).unwrap_or(without_scheme.len()));
+    let user_info = authority.rsplit_once('@').map_or("", |(user_info, _)| user_info);
+    let path_query = path_query.split('#').next().unwrap_or_default();
+    let (path, query) = match path_query.split_once('?') {
+        Some((path, query)) => (path, format!("?{}", query)),
+        None => (path_query, String::new()),
+    };
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, host_port)| host_port);
+    let port = host_port.rsplit_once(':')
+        .map(|(_, port)| port)
+        .filter(|port| !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()))
+        .or(match scheme.to_ascii_lowercase().as_str() {
+            "http" => Some("80"),
+            "https" => Some("443"),
+            "ftp" => Some("21"),
+            _ => None,
+        });
+
+    let value = match part.to_ascii_uppercase().as_str() {
+        "RMVSCM" => without_scheme,
+        "SCM" => scheme,
+        "HOST" => url_pattern::host(url),
+        "PORT" => port.unwrap_or_default(),
+        "PATH" => path,
+        "QUERY" => &query,
+        "USERINFO" => user_info,
+        "USERNAME" => user_info.split(':').next().unwrap_or_default(),
+        "PASSWORD" => user_info.split_once(':').map_or("", |(_, password)| password),
+        _ => return None,
+    };
+    Some(value.to_string())
+}
@@ -0,0 +1,59 @@
+use keepass::db::{Entry, Group};
+use uuid::Uuid;
+
+/// Standard fields of an entry by their code in a field reference
+const FIELDS: [(char, &str); 5] = [('T', "Title"), ('U', "UserName"), ('P', "Password"), ('A', "URL"), ('N', "Notes")];
+
+/// A KeePass field reference `{REF:<wanted>@<search in>:<text>}`
+///
+/// Fields are given by their code: T (title), U (user name), P (password), A (URL),
+/// N (notes) and I (UUID), O (other fields) is only supported for searching.
+pub struct Reference<'a> {
+    wanted: char,
+    search_in: char,
+    text: &'a str,
+}
+
+impl<'a> Reference<'a> {
+    /// Parse a reference including its braces, None if it is not a valid reference
+    pub fn parse(reference: &'a str) -> Option<Reference<'a>> {
+        let inner = &reference[5..reference.len() - 1];
+        let (wanted, search) = inner.split_once('@')?;
+        let (search_in, text) = search.split_once(':')?;
+        let code = |code: &str| match code.to_ascii_uppercase().as_str() {
+            c @ ("T" | "U" | "P" | "A" | "N" | "I" | "O") => c.chars().next(),
+            _ => None,
+        };
+        Some(Reference { wanted: code(wanted).filter(|c| *c != 'O')?, search_in: code(search_in)?, text })
+    }
+
+    /// The first entry whose searched field contains the text, ignoring case, or that has the UUID
+    pub fn find<'g>(&self, root: &'g Group) -> Option<&'g Entry> {
+        if self.search_in == 'I' {
+            return Uuid::parse_str(self.text).ok().and_then(|uuid| root.entry_by_uuid(uuid));
+        }
+        let text = self.text.to_lowercase();
+        find_entry(root, &|entry: &Entry| {
+            let mut values: Vec<&str> = match FIELDS.iter().find(|(code, _)| *code == self.search_in) {
+                Some((_, name)) => entry.get(name).into_iter().collect(),
+                None => entry.fields.keys()
+                    .filter(|name| !FIELDS.iter().any(|(_, standard)| standard == name))
+                    .filter_map(|name| entry.get(name))
+                    .collect(),
+            };
+            values.retain(|value| value.to_lowercase().contains(&text));
+            !values.is_empty()
+        })
+    }
+
+    /// The name of the wanted field, None for the UUID
+    pub fn wanted_field(&self) -> Option<&'static str> {
+        FIELDS.iter().find(|(code, _)| *code == self.wanted).map(|(_, name)| *name)
+    }
+}
+
+/// The first entry below `group` for which `is_match` holds, in tree order
+fn find_entry<'g>(group: &'g Group, is_match: &dyn Fn(&Entry) -> bool) -> Option<&'g Entry> {
+    group.entries.iter().find(|entry| is_match(entry))
+        .or_else(|| group.groups.iter().find_map(|subgroup| find_entry(subgroup, is_match)))
+}
@@ -0,0 +1,469 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::modifiers::Modifier;
+
+/// A parsed secret path: `[group/subgroup/]entry[|field]`, `**/[group/]entry[|field]`,
+/// `uuid:<uuid>[|field]`, `url:<url>[|field]` or `tag:<tag>[+tag:<tag>...][/[group/]entry][|field]`
+///
+/// Any of them may start with a tag filter `[tags=<tag>[,<tag>...]]`, the entry may be
+/// followed by a version `@history[<index>]` or `@at=<time>` and the field by modifiers
+/// `?<modifier>[&<modifier>...]`. Without a field, the modifiers apply to the password
+/// and follow the entry, except for URLs which may contain a `?` themselves.
+/// Within names, `\/`, `\|` and `\\` stand for the literal characters and any
+/// byte may be percent-encoded (`%2F`). Field names may contain unescaped slashes,
+/// an unescaped `@` at their start selects something else than a string field.
+pub struct SecretPath {
+    pub entry: EntrySelector,
+    pub field: Field,
+    /// Tags the entry must have, all of them
+    pub tags: Vec<String>,
+    pub version: Version,
+    /// Transformations of the value, in order
+    pub modifiers: Vec<Modifier>,
+}
+
+/// Which version of the entry a secret path selects
+pub enum Version {
+    /// The current version
+    Current,
+    /// A history item by its index, counting from 0 for the oldest and from -1 for the newest one
+    History(i64),
+    /// The version that was current at a time, written as `@at=<RFC 3339 time>`
+    At(DateTime<Utc>),
+}
+
+/// How the entry of a secret path is found
+pub enum EntrySelector {
+    /// Group names followed by the entry title
+    Path(Vec<String>),
+    /// The end of the path of an entry anywhere in the tree, written as `**/<path>` or `title:<path>`,
+    /// empty to search all entries by tags
+    Search(Vec<String>),
+    /// The UUID of the entry anywhere in the tree, written as `uuid:<uuid>` or `#<uuid>`
+    Uuid(Uuid),
+    /// The URL of the entry anywhere in the tree, written as `url:<url>`: the complete URL,
+    /// its host only or a glob pattern of either
+    Url(String),
+}
+
+/// What of the entry a secret path selects
+pub enum Field {
+    /// A string field by its name
+    Name(String),
+    /// An attachment by its name, written as `@attachment:<name>`
+    Attachment(String),
+    /// The current TOTP code of the entry, written as `@totp`
+    Totp,
+}
+
+impl Field {
+    fn password() -> Field {
+        Field::Name("Password".to_string())
+    }
+}
+
+/// What a name in the secret path refers to, for error messages
+#[derive(Clone, Copy)]
+enum NameKind {
+    Group,
+    Entry,
+    Field,
+}
+
+impl NameKind {
+    fn describe(self) -> &'static str {
+        match self {
+            NameKind::Group => "group name",
+            NameKind::Entry => "entry title",
+            NameKind::Field => "field name",
+        }
+    }
+}
+
+/// The names of a secret path with the field, the version and the modifiers following them
+struct Names {
+    entry_path: Vec<String>,
+    field: Field,
+    version: Version,
+    modifiers: Vec<Modifier>,
+}
+
+impl Names {
+    /// The password of an entry given by other means than names
+    fn password() -> Names {
+        Names { entry_path: Vec::new(), field: Field::password(), version: Version::Current, modifiers: Vec::new() }
+    }
+}
+
+/// A name being parsed, kept as bytes until complete because of percent-encoding
+struct Name {
+    bytes: Vec<u8>,
+    /// Position of the first character of the name
+    start: usize,
+    /// Whether the name starts with an unescaped '@'
+    selector: bool,
+    /// Byte offset and position of the last unescaped '@', which may start a version
+    version_start: Option<(usize, usize)>,
+}
+
+impl Name {
+    fn new(start: usize) -> Name {
+        Name { bytes: Vec::new(), start, selector: false, version_start: None }
+    }
+}
+
+impl SecretPath {
+    fn new(entry: EntrySelector, names: Names) -> SecretPath {
+        SecretPath { entry, field: names.field, tags: Vec::new(), version: names.version, modifiers: names.modifiers }
+    }
+
+    /// Parse the part of `secret_path` starting at byte offset `start`, after a database prefix
+    ///
+    /// Errors point at the offending character of the complete secret path.
+    pub fn parse(secret_path: &str, start: usize) -> Result<SecretPath, String> {
+        let rest = &secret_path[start..];
+        if let Some(filter) = rest.strip_prefix("[tags=") {
+            let filter_start = secret_path.len() - filter.len();
+            let filter_end = match filter.find(']') {
+                Some(end) => filter_start + end,
+                None => return Err(error(secret_path, secret_path[..start].chars().count() + 1,
+                    "unclosed tag filter (expected ']')".to_string())),
+            };
+            let mut tags = Vec::new();
+            let mut tag_start = filter_start;
+            for tag in secret_path[filter_start..filter_end].split(',') {
+                tags.push(parse_tag(secret_path, tag_start, tag.trim())?);
+                tag_start += tag.len() + 1;
+            }
+
+            // A tag filter without selector searches all entries
+            let rest = &secret_path[filter_end + 1..];
+            let mut parsed = if rest.is_empty() || rest.starts_with(['|', '?']) {
+                SecretPath::search_all(secret_path, filter_end + 1)?
+            } else {
+                SecretPath::parse(secret_path, filter_end + 1)?
+            };
+            tags.append(&mut parsed.tags);
+            parsed.tags = tags;
+            return Ok(parsed);
+        }
+
+        if rest.starts_with("tag:") {
+            let mut tags = Vec::new();
+            let mut position = start;
+            loop {
+                let tag_start = match secret_path[position..].strip_prefix("tag:") {
+                    Some(tag) => secret_path.len() - tag.len(),
+                    None => return Err(error(secret_path, secret_path[..position].chars().count() + 1,
+                        "expected 'tag:' after '+'".to_string())),
+                };
+                let tag_end = secret_path[tag_start..].find(['+', '/', '|', '?'])
+                    .map_or(secret_path.len(), |end| tag_start + end);
+                tags.push(parse_tag(secret_path, tag_start, &secret_path[tag_start..tag_end])?);
+                position = tag_end;
+                if !secret_path[position..].starts_with('+') {
+                    break;
+                }
+                position += 1;
+            }
+
+            // The tags may be followed by the end of the path of the entry
+            let mut parsed = match secret_path[position..].strip_prefix('/') {
+                Some(_) => {
+                    let mut names = parse_names(secret_path, position + 1, false)?;
+                    SecretPath::new(EntrySelector::Search(std::mem::take(&mut names.entry_path)), names)
+                }
+                None => SecretPath::search_all(secret_path, position)?,
+            };
+            parsed.tags = tags;
+            return Ok(parsed);
+        }
+
+        if let Some(search) = rest.strip_prefix("**/").or(rest.strip_prefix("title:")) {
+            let mut names = parse_names(secret_path, secret_path.len() - search.len(), false)?;
+            return Ok(SecretPath::new(EntrySelector::Search(std::mem::take(&mut names.entry_path)), names));
+        }
+
+        // URLs are taken literally, their end is the first '|'
+        if let Some(url) = rest.strip_prefix("url:") {
+            let url_start = secret_path.len() - url.len();
+            let (url, mut names) = match url.split_once('|') {
+                Some((url, _)) => (url, parse_names(secret_path, url_start + url.len() + 1, true)?),
+                None => (url, Names::password()),
+            };
+            let (url, version) = split_version(secret_path, url_start, url)?;
+            if url.is_empty() {
+                return Err(error(secret_path, secret_path[..url_start].chars().count() + 1, "empty URL".to_string()));
+            }
+            names.version = version;
+            return Ok(SecretPath::new(EntrySelector::Url(url.to_string()), names));
+        }
+
+        // Only a UUID makes a selector, so titles like '#general' keep working
+        let uuid_text = match rest.strip_prefix("uuid:").or(rest.strip_prefix('#')).filter(|text| starts_with_uuid(text)) {
+            Some(uuid_text) => uuid_text,
+            None => {
+                let mut names = parse_names(secret_path, start, false)?;
+                return Ok(SecretPath::new(EntrySelector::Path(std::mem::take(&mut names.entry_path)), names));
+            }
+        };
+
+        // UUIDs contain no escapes, their end is the first '|' or '?'
+        let uuid_start = secret_path.len() - uuid_text.len();
+        let uuid_end = uuid_text.find(['|', '?']).unwrap_or(uuid_text.len());
+        let mut names = parse_field(secret_path, uuid_start + uuid_end)?;
+        let uuid_text = &uuid_text[..uuid_end];
+        let (uuid_text, version) = split_version(secret_path, uuid_start, uuid_text)?;
+        let uuid = Uuid::parse_str(uuid_text).map_err(|_| error(secret_path,
+            secret_path[..uuid_start].chars().count() + 1, format!("invalid UUID '{}'", uuid_text)))?;
+
+        names.version = version;
+        Ok(SecretPath::new(EntrySelector::Uuid(uuid), names))
+    }
+
+    /// A search of all entries, with the optional field starting at byte offset `start`
+    fn search_all(secret_path: &str, start: usize) -> Result<SecretPath, String> {
+        Ok(SecretPath::new(EntrySelector::Search(Vec::new()), parse_field(secret_path, start)?))
+    }
+}
+
+/// Whether the text of a UUID selector up to the field, modifiers or version is a UUID
+fn starts_with_uuid(text: &str) -> bool {
+    let uuid = text.split(['|', '?', '@']).next().unwrap_or_default();
+    Uuid::parse_str(uuid).is_ok()
+}
+
+/// Split a version off a UUID or URL starting at byte offset `start`, they contain no escapes
+fn split_version<'a>(secret_path: &str, start: usize, text: &'a str) -> Result<(&'a str, Version), String> {
+    let at = match text.rfind('@') {
+        Some(at) => at,
+        None => return Ok((text, Version::Current)),
+    };
+    match parse_version(&text[at..]) {
+        Ok(Some(version)) => Ok((&text[..at], version)),
+        Ok(None) => Ok((text, Version::Current)),
+        Err(message) => Err(error(secret_path, secret_path[..start + at].chars().count() + 1, message)),
+    }
+}
+
+/// Parse a version `@history[<index>]` or `@at=<time>`, None if `suffix` is no version
+fn parse_version(suffix: &str) -> Result<Option<Version>, String> {
+    if let Some(index) = suffix.strip_prefix("@history[") {
+        return match index.strip_suffix(']').and_then(|index| index.parse().ok()) {
+            Some(index) => Ok(Some(Version::History(index))),
+            None => Err(format!("invalid history selector '{}' (expected '@history[<index>]')", suffix)),
+        };
+    }
+    if let Some(time) = suffix.strip_prefix("@at=") {
+        return DateTime::parse_from_rfc3339(time)
+            .map(|time| Some(Version::At(time.to_utc())))
+            .map_err(|_| format!("invalid time '{}' (expected RFC 3339 like 2026-01-01T00:00:00Z)", time));
+    }
+    Ok(None)
+}
+
+/// Check a tag name starting at byte offset `start`, tags are taken literally
+fn parse_tag(secret_path: &str, start: usize, tag: &str) -> Result<String, String> {
+    if tag.is_empty() {
+        return Err(error(secret_path, secret_path[..start].chars().count() + 1, "empty tag".to_string()));
+    }
+    Ok(tag.to_string())
+}
+
+/// Format an entry path as it is written in a secret path, escaping the names
+pub fn format_entry_path(entry_path: &[&str]) -> String {
+    entry_path.iter()
+        .map(|name| name.replace('\\', "\\\\").replace('/', "\\/").replace('|', "\\|").replace('%', "%25"))
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Format a parse error, pointing at the character at `position`
+fn error(secret_path: &str, position: usize, message: String) -> String {
+    format!("{} is no valid secret path: {} at position {}\n    {}\n    {}^\n",
+        secret_path, message, position, secret_path, " ".repeat(position - 1))
+}
+
+/// Parse the names starting at byte offset `start`: the entry path followed by an
+/// optional field, or only the field name if `in_field` is set
+///
+/// Returns the entry path, the field, defaulting to 'Password', the version of the entry
+/// and the modifiers following the field.
+fn parse_names(secret_path: &str, start: usize, mut in_field: bool) -> Result<Names, String> {
+    let error = |position: usize, message: String| error(secret_path, position, message);
+
+    // Positions are counted in characters, starting at 1
+    let offset = secret_path[..start].chars().count();
+    let mut chars = secret_path[start..].chars().enumerate()
+        .map(|(index, c)| (offset + index + 1, c));
+
+    let mut entry_path = Vec::new();
+    let mut name = Name::new(offset + 1);
+    let mut version = Version::Current;
+    let mut modifiers = Vec::new();
+    let mut end = offset + secret_path[start..].chars().count() + 1;
+
+    let finish = |name: &Name, kind: NameKind, position: usize| -> Result<String, String> {
+        if name.bytes.is_empty() {
+            return Err(error(position, format!("empty {}", kind.describe())));
+        }
+        String::from_utf8(name.bytes.clone())
+            .map_err(|_| error(name.start, format!("percent-encoded {} is not valid UTF-8", kind.describe())))
+    };
+
+    while let Some((position, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, escaped @ ('/' | '|' | '\\'))) => push_char(&mut name.bytes, escaped),
+                Some((_, other)) => return Err(error(position, format!(
+                    "invalid escape sequence '\\{}' (only '\\/', '\\|' and '\\\\' are supported)", other))),
+                None => return Err(error(position, "incomplete escape sequence".to_string())),
+            },
+            '%' => {
+                let digits: String = [chars.next(), chars.next()].iter().flatten().map(|(_, c)| *c).collect();
+                match decode_percent(&digits) {
+                    Some(byte) => name.bytes.push(byte),
+                    None => return Err(error(position, format!(
+                        "invalid percent-encoding '%{}' (use '%25' for a literal '%')", digits))),
+                }
+            }
+            '|' if in_field => {
+                return Err(error(position, "unexpected '|' (escape it as '\\|' to use it in a name)".to_string()));
+            }
+            '|' => {
+                version = take_version(&mut name).map_err(|(position, message)| error(position, message))?;
+                entry_path.push(finish(&name, NameKind::Entry, position)?);
+                name = Name::new(position + 1);
+                in_field = true;
+            }
+            '/' if !in_field => {
+                entry_path.push(finish(&name, NameKind::Group, position)?);
+                name = Name::new(position + 1);
+            }
+            '@' if in_field && name.bytes.is_empty() && !name.selector => name.selector = true,
+            '?' => {
+                let modifiers_start = secret_path.char_indices().nth(position).map_or(secret_path.len(), |(i, _)| i);
+                modifiers = parse_modifiers(secret_path, modifiers_start)?;
+                end = position;
+                break;
+            }
+            '@' if !in_field => {
+                name.version_start = Some((name.bytes.len(), position));
+                push_char(&mut name.bytes, c);
+            }
+            _ => push_char(&mut name.bytes, c),
+        }
+    }
+
+    let field = if in_field && name.selector {
+        let selector = finish(&name, NameKind::Field, end)?;
+        match selector.split_once(':') {
+            Some(("attachment", attachment)) if !attachment.is_empty() => Field::Attachment(attachment.to_string()),
+            None if selector == "totp" => Field::Totp,
+            _ => return Err(error(name.start, format!(
+                "unknown field selector '@{}' (expected '@attachment:<name>' or '@totp', use '%40' for a literal '@')", selector))),
+        }
+    } else if in_field {
+        Field::Name(finish(&name, NameKind::Field, end)?)
+    } else {
+        version = take_version(&mut name).map_err(|(position, message)| error(position, message))?;
+        entry_path.push(finish(&name, NameKind::Entry, end)?);
+        Field::password()
+    };
+
+    Ok(Names { entry_path, field, version, modifiers })
+}
+
+/// Parse what follows an entry given by other means than names at byte offset `start`:
+/// a field after '|', modifiers of the password after '?' or nothing
+fn parse_field(secret_path: &str, start: usize) -> Result<Names, String> {
+    match secret_path[start..].chars().next() {
+        Some('|') => parse_names(secret_path, start + 1, true),
+        Some('?') => Ok(Names { modifiers: parse_modifiers(secret_path, start + 1)?, ..Names::password() }),
+        _ => Ok(Names::password()),
+    }
+}
+
+/// Parse the modifiers `<modifier>[&<modifier>...]` starting at byte offset `start`
+fn parse_modifiers(secret_path: &str, start: usize) -> Result<Vec<Modifier>, String> {
+    let mut modifiers = Vec::new();
+    let mut modifier_start = start;
+    for modifier in secret_path[start..].split('&') {
+        let position = secret_path[..modifier_start].chars().count() + 1;
+        if modifier.is_empty() {
+            return Err(error(secret_path, position, "empty modifier".to_string()));
+        }
+        modifiers.push(Modifier::parse(modifier).map_err(|message| error(secret_path, position, message))?);
+        modifier_start += modifier.len() + 1;
+    }
+    Ok(modifiers)
+}
+
+/// Take a version off the end of an entry name, the error with its position
+fn take_version(name: &mut Name) -> Result<Version, (usize, String)> {
+    let (byte, position) = match name.version_start {
+        Some(start) => start,
+        None => return Ok(Version::Current),
+    };
+    let suffix = String::from_utf8_lossy(&name.bytes[byte..]).into_owned();
+    match parse_version(&suffix).map_err(|message| (position, message))? {
+        Some(version) => {
+            name.bytes.truncate(byte);
+            Ok(version)
+        }
+        None => Ok(Version::Current),
+    }
+}
+
+/// Parse a group path `group/subgroup` configured outside a secret path, with the
+/// same escapes as its names
+///
+/// Whitespace around the names is ignored, as are empty names.
+pub fn parse_group_path(path: &str) -> Result<Vec<String>, String> {
+    let mut names = Vec::new();
+    let mut name = Vec::new();
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped @ ('/' | '|' | '\\')) => push_char(&mut name, escaped),
+                Some(other) => return Err(format!(
+                    "invalid escape sequence '\\{}' (only '\\/', '\\|' and '\\\\' are supported)", other)),
+                None => return Err("incomplete escape sequence".to_string()),
+            },
+            '%' => {
+                let digits: String = chars.by_ref().take(2).collect();
+                match decode_percent(&digits) {
+                    Some(byte) => name.push(byte),
+                    None => return Err(format!("invalid percent-encoding '%{}' (use '%25' for a literal '%')", digits)),
+                }
+            }
+            '/' => names.push(std::mem::take(&mut name)),
+            _ => push_char(&mut name, c),
+        }
+    }
+    names.push(name);
+
+    let mut group_path = Vec::new();
+    for name in names {
+        let name = String::from_utf8(name).map_err(|_| "percent-encoded group name is not valid UTF-8".to_string())?;
+        if !name.trim().is_empty() {
+            group_path.push(name.trim().to_string());
+        }
+    }
+    Ok(group_path)
+}
+
+/// The byte of the two hex digits of a percent-encoding
+fn decode_percent(digits: &str) -> Option<u8> {
+    // from_str_radix alone would accept a sign like in '%+1'
+    if digits.len() != 2 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u8::from_str_radix(digits, 16).ok()
+}
+
+fn push_char(bytes: &mut Vec<u8>, c: char) {
+    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
@@ -0,0 +1,156 @@
+use hmac::{Hmac, Mac};
+use keepass::db::Entry;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+use crate::modifiers::percent_decode;
+
+/// Characters of Steam Guard codes
+const STEAM_CHARS: &[u8] = b"23456789BCDFGHJKMNPQRTVWXY";
+
+/// Hash algorithm of a TOTP configuration
+#[derive(Clone, Copy)]
+enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+/// The TOTP configuration of an entry
+pub struct Totp {
+    secret: Vec<u8>,
+    algorithm: Algorithm,
+    digits: u32,
+    period: u64,
+    /// Steam Guard codes of 5 characters instead of digits
+    steam: bool,
+}
+
+impl Totp {
+    /// Read the TOTP configuration of an entry, None if it has none
+    ///
+    /// KeePassXC stores an `otpauth://totp/` URI in the `otp` field, older versions
+    /// the secret in `TOTP Seed` and `<period>;<digits>` in `TOTP Settings`, where
+    /// the digits are `S` for Steam.
+    pub fn from_entry(entry: &Entry) -> Result<Option<Totp>, String> {
+        if let Some(uri) = entry.get("otp") {
+            return parse_uri(uri).map(Some);
+        }
+        let seed = match entry.get("TOTP Seed") {
+            Some(seed) => seed,
+            None => return Ok(None),
+        };
+        let mut totp = Totp { secret: decode_secret(seed)?, algorithm: Algorithm::Sha1, digits: 6, period: 30, steam: false };
+        if let Some(settings) = entry.get("TOTP Settings") {
+            let mut settings = settings.split(';');
+            totp.period = parse_number(settings.next(), "period")?.unwrap_or(30);
+            match settings.next().map(|digits| digits.trim()) {
+                Some("S") => totp.steam = true,
+                digits => totp.digits = parse_number(digits, "digits")?.unwrap_or(6),
+            }
+        }
+        totp.validate().map(Some)
+    }
+
+    /// The code at a time given in seconds since the epoch
+    pub fn code(&self, time: i64) -> String {
+        let counter = (time.max(0) as u64 / self.period).to_be_bytes();
+        let hash = match self.algorithm {
+            Algorithm::Sha1 => hmac::<Hmac<Sha1>>(&self.secret, &counter),
+            Algorithm::Sha256 => hmac::<Hmac<Sha256>>(&self.secret, &counter),
+            Algorithm::Sha512 => hmac::<Hmac<Sha512>>(&self.secret, &counter),
+        };
+
+        // Dynamic truncation as of RFC 4226
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let mut value = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+        if self.steam {
+            let mut code = String::new();
+            for _ in 0..5 {
+                code.push(STEAM_CHARS[(value % STEAM_CHARS.len() as u32) as usize] as char);
+                value /= STEAM_CHARS.len() as u32;
+            }
+            return code;
+        }
+        format!("{:0width$}", value as u64 % 10u64.pow(self.digits), width = self.digits as usize)
+    }
+
+    fn validate(self) -> Result<Totp, String> {
+        if self.period == 0 {
+            return Err("invalid TOTP period 0".to_string());
+        }
+        if !(1..=10).contains(&self.digits) {
+            return Err(format!("invalid number of TOTP digits {}", self.digits));
+        }
+        Ok(self)
+    }
+}
+
+/// Parse an `otpauth://totp/<label>?secret=<base32>[&algorithm=..][&digits=..][&period=..][&encoder=steam]` URI
+fn parse_uri(uri: &str) -> Result<Totp, String> {
+    let query = match uri.trim().strip_prefix("otpauth://totp/") {
+        Some(rest) => rest.split_once('?').map_or("", |(_, query)| query),
+        None => return Err("the otp field is no otpauth://totp/ URI".to_string()),
+    };
+
+    let mut secret = None;
+    let mut totp = Totp { secret: Vec::new(), algorithm: Algorithm::Sha1, digits: 6, period: 30, steam: false };
+    for parameter in query.split('&') {
+        let (name, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+        let value = String::from_utf8_lossy(&percent_decode(value.as_bytes())).into_owned();
+        match name {
+            "secret" => secret = Some(decode_secret(&value)?),
+            "algorithm" => totp.algorithm = match value.to_ascii_uppercase().as_str() {
+                "SHA1" => Algorithm::Sha1,
+                "SHA256" => Algorithm::Sha256,
+                "SHA512" => Algorithm::Sha512,
+                other => return Err(format!("unsupported TOTP algorithm '{}'", other)),
+            },
+            "digits" => totp.digits = parse_number(Some(&value), "digits")?.unwrap_or(6),
+            "period" => totp.period = parse_number(Some(&value), "period")?.unwrap_or(30),
+            "encoder" => totp.steam = value.eq_ignore_ascii_case("steam"),
+            _ => {}
+        }
+    }
+    totp.secret = secret.ok_or("the otp field has no secret")?;
+    totp.validate()
+}
+
+/// Parse an optional number of a TOTP setting, None if it is empty
+fn parse_number<T: std::str::FromStr>(value: Option<&str>, setting: &str) -> Result<Option<T>, String> {
+    match value.map(|value| value.trim()).filter(|value| !value.is_empty()) {
+        Some(value) => value.parse().map(Some).map_err(|_| format!("invalid TOTP {} '{}'", setting, value)),
+        None => Ok(None),
+    }
+}
+
+/// Decode a base32 TOTP secret, ignoring case, spaces and padding
+fn decode_secret(secret: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in secret.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u32 - 'A' as u32,
+            c @ '2'..='7' => c as u32 - '2' as u32 + 26,
+            _ => return Err("invalid TOTP secret, expected base32".to_string()),
+        };
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bytes.is_empty() {
+        return Err("empty TOTP secret".to_string());
+    }
+    Ok(bytes)
+}
+
+/// The HMAC of a message with the hash function of `M`
+fn hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = <M as hmac::digest::KeyInit>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
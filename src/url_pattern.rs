@@ -0,0 +1,63 @@
+/// Whether the URL field of an entry matches the URL of a `url:` selector
+///
+/// The pattern is compared with the complete URL if it contains a scheme (`://`),
+/// otherwise with the host of the URL, ignoring case. Patterns containing `*` or `?`
+/// are glob patterns, `*` matching any number of characters and `?` a single one.
+pub fn matches(pattern: &str, url: &str) -> bool {
+    let glob = pattern.contains(['*', '?']);
+    if pattern.contains("://") {
+        if glob { glob_matches(pattern, url) } else { pattern == url }
+    } else {
+        let pattern = pattern.to_lowercase();
+        let host = host(url).to_lowercase();
+        if glob { glob_matches(&pattern, &host) } else { pattern == host }
+    }
+}
+
+/// The host of a URL, without scheme, user info, port and path
+///
+/// URLs without scheme, as often stored in KeePass, start with the host.
+pub fn host(url: &str) -> &str {
+    let authority = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = authority.split(['/', '?', '#']).next().unwrap_or_default();
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, host_port)| host_port);
+    if let Some(ipv6) = host_port.strip_prefix('[') {
+        return ipv6.split(']').next().unwrap_or_default();
+    }
+    host_port.split(':').next().unwrap_or_default()
+}
+
+/// Match `text` against a glob pattern of `*` and `?` wildcards
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Backtrack to the last '*' when the rest does not match
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(c) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
@@ -117,3 +117,149 @@ pub fn run_with_env_override(args: &[&str], env_path: Option<&str>, env_pass: Op
 
     (stdout, stderr, exit_code)
 }
+
+/// Get the absolute path to a file in the test fixtures directory
+pub fn get_fixture_path(name: &str) -> PathBuf {
+    let mut path = std::env::current_dir().expect("Failed to get current directory");
+    path.push("tests/fixtures");
+    path.push(name);
+    path
+}
+
+/// Run summon-keepass with the given environment variables only (no config file)
+pub fn run_with_env_vars(args: &[&str], env_vars: &[(&str, &str)]) -> (String, String, i32) {
+    let mut cmd = Command::cargo_bin("summon-keepass").expect("Failed to find binary");
+
+    // Set HOME to a nonexistent directory to ensure no config file is used
+    cmd.env("HOME", "/tmp/nonexistent-summon-keepass-test");
+    for (key, value) in env_vars {
+        cmd.env(key, value);
+    }
+
+    cmd.args(args);
+
+    let output = cmd.output().expect("Failed to execute command");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let exit_code = output.status.code().unwrap_or(-1);
+
+    (stdout, stderr, exit_code)
+}
+
+/// Run summon-keepass against a fixture database unlocked by the password test123
+pub fn run_with_fixture_database(args: &[&str], fixture_name: &str) -> (String, String, i32) {
+    run_with_fixture_database_and_env(args, fixture_name, &[])
+}
+
+/// Run summon-keepass against a fixture database with additional environment variables
+pub fn run_with_fixture_database_and_env(args: &[&str], fixture_name: &str, env_vars: &[(&str, &str)]) -> (String, String, i32) {
+    let db_path = get_fixture_path(fixture_name);
+    let mut all_env_vars = vec![
+        ("SUMMON_KEEPASS_DB_PATH", db_path.to_str().unwrap()),
+        ("SUMMON_KEEPASS_DB_PASS", "test123"),
+    ];
+    all_env_vars.extend_from_slice(env_vars);
+    run_with_env_vars(args, &all_env_vars)
+}
+
+/// Run summon-keepass against a fixture database, returning stdout unchanged for binary values
+pub fn run_with_fixture_database_raw(args: &[&str], fixture_name: &str) -> (Vec<u8>, String, i32) {
+    let mut cmd = Command::cargo_bin("summon-keepass").expect("Failed to find binary");
+
+    cmd.env("HOME", "/tmp/nonexistent-summon-keepass-test");
+    cmd.env("SUMMON_KEEPASS_DB_PATH", get_fixture_path(fixture_name));
+    cmd.env("SUMMON_KEEPASS_DB_PASS", "test123");
+    cmd.args(args);
+
+    let output = cmd.output().expect("Failed to execute command");
+
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let exit_code = output.status.code().unwrap_or(-1);
+
+    (output.stdout, stderr, exit_code)
+}
+
+/// Run summon-keepass with a custom config file content (no environment variables)
+pub fn run_with_config_content(args: &[&str], config_content: &str) -> (String, String, i32) {
+    run_with_config_and_env(args, config_content, &[])
+}
+
+/// Run summon-keepass with a custom config file content and the given environment variables
+pub fn run_with_config_and_env(args: &[&str], config_content: &str, env_vars: &[(&str, &str)]) -> (String, String, i32) {
+    let temp_home = TempDir::new().expect("Failed to create temp directory");
+    let config_path = temp_home.path().join(".summon-keepass.ini");
+    fs::write(&config_path, config_content).expect("Failed to write test config");
+
+    let mut cmd = Command::cargo_bin("summon-keepass").expect("Failed to find binary");
+    cmd.env("HOME", temp_home.path());
+    for (key, value) in env_vars {
+        cmd.env(key, value);
+    }
+    cmd.args(args);
+
+    let output = cmd.output().expect("Failed to execute command");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let exit_code = output.status.code().unwrap_or(-1);
+
+    (stdout, stderr, exit_code)
+}
+
+/// A running `summon-keepass --agent` serving the test database, killed when dropped
+pub struct Agent {
+    child: std::process::Child,
+    pub socket_path: PathBuf,
+    _socket_dir: TempDir,
+}
+
+impl Drop for Agent {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Agent {
+    /// Whether the agent process is still running
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+/// Start an agent for the test database with the given extra arguments and wait until it listens
+pub fn start_agent(extra_args: &[&str]) -> Agent {
+    start_agent_with_env(extra_args, &[("HOME", "/tmp/nonexistent-summon-keepass-test")])
+}
+
+/// Start an agent for the test database with additional environment variables
+pub fn start_agent_with_env(extra_args: &[&str], env_vars: &[(&str, &str)]) -> Agent {
+    let socket_dir = TempDir::new().expect("Failed to create temp directory");
+    let socket_path = socket_dir.path().join("agent.sock");
+
+    let child = std::process::Command::new(assert_cmd::cargo::cargo_bin("summon-keepass"))
+        .env("SUMMON_KEEPASS_DB_PATH", get_test_db_path())
+        .env("SUMMON_KEEPASS_DB_PASS", "test123")
+        .envs(env_vars.iter().copied())
+        .arg("--agent")
+        .arg("--socket")
+        .arg(&socket_path)
+        .args(extra_args)
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .expect("Failed to start agent");
+
+    let mut agent = Agent { child, socket_path, _socket_dir: socket_dir };
+
+    // Unlocking the database takes a while, the socket appears afterwards
+    for _ in 0..600 {
+        if agent.socket_path.exists() || !agent.is_running() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert!(agent.socket_path.exists(), "Agent did not create its socket");
+
+    agent
+}